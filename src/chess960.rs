@@ -0,0 +1,178 @@
+use game::Color::{Black, White};
+use game::PieceType::{Bishop, King, Knight, Queen, Rook};
+use game::*;
+
+pub const POSITIONS: u32 = 960;
+
+// Where the two knights go among the five squares left after placing the bishops and the queen
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+// The back rank of a start position in Scharnagl's numbering, where 518 is the classical one
+pub fn back_rank(index: u32) -> Option<[PieceType; 8]> {
+    if index >= POSITIONS {
+        return None;
+    }
+    let mut rank = [None; 8];
+    let mut n = index as usize;
+    rank[2 * (n % 4) + 1] = Some(Bishop);
+    n /= 4;
+    rank[2 * (n % 4)] = Some(Bishop);
+    n /= 4;
+    let place = |rank: &mut [Option<PieceType>; 8], nth: usize, tipo| {
+        let file = (0..8).filter(|&f| rank[f].is_none()).nth(nth).unwrap();
+        rank[file] = Some(tipo);
+    };
+    place(&mut rank, n % 6, Queen);
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    // The second knight goes first so that placing it doesn't shift the empty squares
+    place(&mut rank, second, Knight);
+    place(&mut rank, first, Knight);
+    for &tipo in [Rook, King, Rook].iter() {
+        place(&mut rank, 0, tipo);
+    }
+    let mut pieces = [King; 8];
+    for (piece, square) in pieces.iter_mut().zip(rank.iter()) {
+        *piece = square.unwrap();
+    }
+    Some(pieces)
+}
+
+impl Game {
+    pub fn new_chess960(index: u32) -> Option<Game> {
+        let rank = back_rank(index)?;
+        let mut game = Game::new();
+        game.clear();
+        for (i, &tipo) in rank.iter().enumerate() {
+            let x = Position::ch2x('a') + i as X;
+            for &(color, y) in [(White, '1'), (Black, '8')].iter() {
+                let pos = Position::new(x, Position::ch2y(y));
+                game.set_square(pos, Some(Piece::new(color, tipo))).unwrap();
+                let pawn_rank = if color == White { '2' } else { '7' };
+                game.set_square(
+                    Position::new(x, Position::ch2y(pawn_rank)),
+                    Some(Piece::new(color, PieceType::Pawn)),
+                )
+                .unwrap();
+            }
+        }
+        let files: Vec<X> = (0..8)
+            .filter(|&i| rank[i] == Rook || rank[i] == King)
+            .map(|i| Position::ch2x('a') + i as X)
+            .collect();
+        game.castling = CastlingRights {
+            long_rook_file: files[0],
+            king_file: files[1],
+            short_rook_file: files[2],
+            ..CastlingRights::all()
+        };
+        game.turn = White;
+        game.en_passant = None;
+        game.halfmove_clock = 0;
+        game.fullmove_number = 1;
+        game.chess960 = true;
+        Some(game)
+    }
+}
+
+#[test]
+fn test_start_positions() {
+    let fen = |index| Game::new_chess960(index).unwrap().to_fen();
+    assert_eq!(fen(518), Game::new().to_fen());
+    assert_eq!(
+        fen(0),
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+    );
+    assert_eq!(
+        Game::new_chess960(0).unwrap().to_shredder_fen(),
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+    );
+    assert_eq!(
+        fen(959),
+        "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+    );
+    assert!(Game::new_chess960(960).is_none());
+    for index in 0..POSITIONS {
+        let game = Game::new_chess960(index).unwrap();
+        assert!(game.validate().is_empty(), "{}", index);
+        assert_eq!(
+            Game::from_fen(&game.to_shredder_fen()).unwrap().to_fen(),
+            game.to_fen()
+        );
+    }
+}
+
+#[test]
+fn test_chess960_castling() {
+    // King on b1 with rooks on a1 and c1
+    let mut game = Game::from_fen("rkr5/ppp5/8/8/8/8/PPP5/RKR5 w CAca - 0 1").unwrap();
+    assert!(game.chess960);
+    assert_eq!(game.to_fen(), "rkr5/ppp5/8/8/8/8/PPP5/RKR5 w KQkq - 0 1");
+    let short = game.parse_move("b1c1").unwrap();
+    assert_eq!(short.tipo, MoveType::ShortCastling);
+    assert_eq!(game.format_move(&short), "b1c1");
+    assert_eq!(game.format_san(&short), "O-O");
+    assert_eq!(
+        game.parse_move("b1a1").unwrap().tipo,
+        MoveType::LongCastling
+    );
+    // The king would land on c1, where the other rook stands
+    assert_eq!(
+        game.clone().make_move(&game.parse_move("b1a1").unwrap()),
+        Err(MoveError::BadLongCastling)
+    );
+    game.make_move(&short).unwrap();
+    assert_eq!(game.to_fen(), "rkr5/ppp5/8/8/8/8/PPP5/R4RK1 b kq - 1 1");
+    assert_eq!(game.to_shredder_fen(), "rkr5/ppp5/8/8/8/8/PPP5/R4RK1 b ca - 1 1");
+    let long = game.parse_move("b8a8").unwrap();
+    assert_eq!(long.tipo, MoveType::LongCastling);
+    assert_eq!(game.make_move(&long), Err(MoveError::BadLongCastling));
+}
+
+#[test]
+fn test_castling_rules() {
+    // A piece between the king and its destination
+    let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K1NR w KQ - 0 1").unwrap();
+    assert!(!game.chess960);
+    assert_eq!(
+        game.clone().make_move(&Move::safe_from_string("O-O")),
+        Err(MoveError::BadShortCastling)
+    );
+    assert!(game
+        .clone()
+        .make_move(&Move::safe_from_string("O-O-O"))
+        .is_ok());
+    // b1 may be attacked, the king only crosses d1 and c1
+    let game = Game::from_fen("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+    assert!(game
+        .clone()
+        .make_move(&Move::safe_from_string("O-O-O"))
+        .is_ok());
+    // No castling out of, through or into check
+    for fen in [
+        "4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1",
+        "4k3/5r2/8/8/8/8/8/R3K2R w KQ - 0 1",
+        "4k3/6r1/8/8/8/8/8/R3K2R w KQ - 0 1",
+    ]
+    .iter()
+    {
+        let game = Game::from_fen(fen).unwrap();
+        assert_eq!(
+            game.clone().make_move(&Move::safe_from_string("O-O")),
+            Err(MoveError::BadShortCastling),
+            "{}",
+            fen
+        );
+    }
+}
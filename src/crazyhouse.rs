@@ -0,0 +1,232 @@
+use eval::EvalParams;
+use game::Color::{Black, White};
+use game::PieceType::{Bishop, Knight, Pawn, Queen, Rook};
+use game::*;
+use variant::Rules;
+
+// The kinds of pieces that can be in a pocket, in the order FENs list them
+pub const POCKET_PIECES: [PieceType; 5] = [Queen, Rook, Bishop, Knight, Pawn];
+
+// Pieces a player has captured and can drop back on the board as their own
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Pocket {
+    counts: [u32; 5],
+}
+
+impl Pocket {
+    fn slot(tipo: PieceType) -> usize {
+        POCKET_PIECES
+            .iter()
+            .position(|&pt| pt == tipo)
+            .expect("Kings never go to a pocket")
+    }
+
+    pub fn count(&self, tipo: PieceType) -> u32 {
+        self.counts[Pocket::slot(tipo)]
+    }
+
+    pub fn add(&mut self, tipo: PieceType) {
+        self.counts[Pocket::slot(tipo)] += 1;
+    }
+
+    pub fn remove(&mut self, tipo: PieceType) -> bool {
+        let count = &mut self.counts[Pocket::slot(tipo)];
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|&count| count == 0)
+    }
+
+    // Every piece in the pocket, repeated as many times as it's there
+    pub fn pieces(&self) -> Vec<PieceType> {
+        POCKET_PIECES
+            .iter()
+            .zip(self.counts.iter())
+            .flat_map(|(&tipo, &count)| (0..count).map(move |_| tipo))
+            .collect()
+    }
+}
+
+fn promoted_bit(pos: Position) -> u64 {
+    1 << pos.index()
+}
+
+impl Game {
+    pub fn is_promoted(&self, pos: Position) -> bool {
+        self.promoted & promoted_bit(pos) != 0
+    }
+
+    pub fn set_promoted(&mut self, pos: Position, promoted: bool) {
+        if promoted {
+            self.promoted |= promoted_bit(pos);
+        } else {
+            self.promoted &= !promoted_bit(pos);
+        }
+    }
+
+    // Puts a piece from the pocket of the side to move on an empty square. Pawns can't go on
+    // the first or last rank
+    pub fn drop_piece(&mut self, tipo: PieceType, to: Position) -> Result<(), MoveError> {
+        let color = self.turn;
+        let back_rank = to.y == Position::ch2y('1') || to.y == Position::ch2y('8');
+        let empty = match self.get_raw_square(to) {
+            Some(square) => square.content.is_none(),
+            None => false,
+        };
+        if !empty || (tipo == Pawn && back_rank) || self.pockets[color.index()].count(tipo) == 0 {
+            return Err(MoveError::BadDrop);
+        }
+        let mut aux = self.clone();
+        aux.set_square(to, Some(Piece::new(color, tipo)))?;
+        aux.turn = !color;
+        if !self.rules().is_legal(&aux) {
+            return Err(MoveError::KingInCheck);
+        }
+        self.set_square(to, Some(Piece::new(color, tipo)))?;
+        self.pockets[color.index()].remove(tipo);
+        self.turn = !color;
+        Ok(())
+    }
+}
+
+// Captured pieces change sides and go to the pocket of the capturer, pieces that came from a
+// promotion go back to being pawns. Dropping a piece is a move
+pub struct Crazyhouse;
+
+impl Rules for Crazyhouse {
+    fn generate_moves(&self, game: &Game) -> Vec<ValuedMove> {
+        let mut moves = game.standard_moves();
+        let pocket = game.pockets[game.turn.index()];
+        for &tipo in POCKET_PIECES.iter().filter(|&&tipo| pocket.count(tipo) > 0) {
+            for to in Position::all().filter(|&pos| game.get_piece(pos).is_none()) {
+                let back_rank = to.y == Position::ch2y('1') || to.y == Position::ch2y('8');
+                if tipo != Pawn || !back_rank {
+                    moves.push(ValuedMove::new(to, to, MoveType::Drop(tipo)));
+                }
+            }
+        }
+        moves
+    }
+
+    // The promoted squares still describe the position before the move here
+    fn after_move(&self, game: &mut Game, mov: &Move, captured: Option<Piece>) {
+        if let Some(piece) = captured {
+            let tipo = if game.is_promoted(mov.to) {
+                Pawn
+            } else {
+                piece.tipo
+            };
+            game.pockets[(!game.turn).index()].add(tipo);
+        }
+        match mov.tipo {
+            MoveType::Normal => {
+                let promoted = game.is_promoted(mov.from);
+                game.set_promoted(mov.from, false);
+                game.set_promoted(mov.to, promoted);
+            }
+            MoveType::Promotion(_) => {
+                game.set_promoted(mov.from, false);
+                game.set_promoted(mov.to, true);
+            }
+            _ => {}
+        }
+    }
+
+    // Pieces in the pocket are worth as much as those on the board
+    fn evaluate(&self, game: &Game, params: &EvalParams) -> i32 {
+        let pockets: i32 = [White, Black]
+            .iter()
+            .map(|&color| {
+                game.pockets[color.index()]
+                    .pieces()
+                    .iter()
+                    .map(|&tipo| color.get_sign() * params.piece_value(tipo))
+                    .sum::<i32>()
+            })
+            .sum();
+        game.standard_evaluation(params) + pockets
+    }
+}
+
+#[test]
+fn test_pocket() {
+    let mut pocket = Pocket::default();
+    assert!(pocket.is_empty());
+    pocket.add(Pawn);
+    pocket.add(Queen);
+    pocket.add(Pawn);
+    assert_eq!(pocket.count(Pawn), 2);
+    assert_eq!(pocket.pieces(), vec![Queen, Pawn, Pawn]);
+    assert!(pocket.remove(Queen));
+    assert!(!pocket.remove(Queen));
+    assert!(!pocket.remove(Rook));
+    assert_eq!(pocket.pieces(), vec![Pawn, Pawn]);
+}
+
+#[test]
+fn test_crazyhouse() {
+    use variant::Variant;
+    let mut game = Variant::Crazyhouse.start_position();
+    for mov in ["e2e4", "d7d5", "e4d5", "d8d5"].iter() {
+        game.make_move(&game.parse_move(mov).unwrap()).unwrap();
+    }
+    assert_eq!(game.pockets[0].pieces(), vec![Pawn]);
+    assert_eq!(game.pockets[1].pieces(), vec![Pawn]);
+    assert_eq!(
+        game.to_fen(),
+        "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w KQkq - 0 3"
+    );
+    let drop = game.parse_move("P@d4").unwrap();
+    assert_eq!(drop.tipo, MoveType::Drop(Pawn));
+    assert_eq!(drop.to_string(), "P@d4");
+    assert!(game.legal_moves().contains(&drop));
+    assert!(!game.legal_moves().contains(&Move::safe_from_string("P@d8")));
+    assert_eq!(game.format_san(&drop), "P@d4");
+    assert_eq!(game.parse_san("P@d4"), Ok(drop));
+    game.make_move(&drop).unwrap();
+    assert!(game.pockets[0].is_empty());
+    // The pocket of black has no knight
+    assert_eq!(
+        game.clone().make_move(&Move::safe_from_string("N@e4")),
+        Err(MoveError::BadDrop)
+    );
+    // Nor can a drop go on an occupied square
+    assert_eq!(
+        game.clone().make_move(&Move::safe_from_string("P@d4")),
+        Err(MoveError::BadDrop)
+    );
+
+    // A promoted queen goes back to a pawn once captured
+    let mut game = Game::from_fen("4k3/1P6/8/8/8/8/8/4K3[] w - - 0 1").unwrap();
+    assert_eq!(game.variant, Variant::Crazyhouse);
+    game.make_move(&Move::safe_from_string("b7b8q")).unwrap();
+    assert_eq!(game.to_fen(), "1Q~2k3/8/8/8/8/8/8/4K3[] b - - 0 1");
+    assert_eq!(
+        Game::from_fen(&game.to_fen()).unwrap().to_fen(),
+        game.to_fen()
+    );
+    game.make_move(&Move::safe_from_string("e8d7")).unwrap();
+    game.make_move(&Move::safe_from_string("b8c8")).unwrap();
+    assert!(game.is_promoted(Position::safe_from_chars('c', '8')));
+    game.make_move(&Move::safe_from_string("d7c8")).unwrap();
+    assert_eq!(game.pockets[1].pieces(), vec![Pawn]);
+    assert!(!game.is_promoted(Position::safe_from_chars('c', '8')));
+}
+
+#[test]
+fn test_search_crazyhouse() {
+    use variant::Variant;
+    // Mate by dropping the queen on the back rank
+    let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/6K1[Q] w - - 0 1").unwrap();
+    assert_eq!(game.variant, Variant::Crazyhouse);
+    let result = ::lurri::Engine::new().get_move(&game, 2);
+    assert_eq!(result.score, BoardValue::WonWhite(1));
+    let mov = result.best_move.unwrap();
+    assert_eq!(mov.tipo, MoveType::Drop(Queen));
+    assert_eq!(mov.to.y, Position::ch2y('8'));
+}
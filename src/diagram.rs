@@ -0,0 +1,190 @@
+use fen::FenError;
+use game::Color::{Black, White};
+use game::PieceType::King;
+use game::*;
+use render::{glyph, RenderOptions};
+
+const SQUARE: i32 = 45;
+// Room around the board for the coordinates
+const MARGIN: i32 = 20;
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const LAST_MOVE_SQUARE: &str = "rgba(155, 199, 0, 0.41)";
+const CHECK_SQUARE: &str = "rgba(220, 40, 40, 0.6)";
+const ARROW: &str = "rgba(21, 120, 27, 0.8)";
+
+impl Game {
+    // A self-contained SVG picture of the position. The orientation, coordinates and
+    // highlighting of the render options apply, the last move also gets an arrow
+    pub fn svg(&self, options: &RenderOptions) -> String {
+        let board = 8 * SQUARE;
+        let size = board + if options.coordinates { 2 * MARGIN } else { 0 };
+        let offset = if options.coordinates { MARGIN } else { 0 };
+        // Top left corner of a square
+        let corner = |pos: Position| {
+            let (file, rank) = ((pos.x - 2) as i32, (pos.y - 2) as i32);
+            match options.orientation {
+                White => (offset + file * SQUARE, offset + (7 - rank) * SQUARE),
+                Black => (offset + (7 - file) * SQUARE, offset + rank * SQUARE),
+            }
+        };
+        let center = |pos: Position| {
+            let (x, y) = corner(pos);
+            (x + SQUARE / 2, y + SQUARE / 2)
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" \
+             viewBox=\"0 0 {0} {0}\">\n",
+            size
+        );
+        svg.push_str(&format!(
+            "<defs><marker id=\"arrowhead\" viewBox=\"0 0 10 10\" refX=\"5\" refY=\"5\" \
+             markerWidth=\"3\" markerHeight=\"3\" orient=\"auto\">\
+             <path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"{}\"/></marker></defs>\n",
+            ARROW
+        ));
+        svg.push_str(&format!(
+            "<rect width=\"{0}\" height=\"{0}\" fill=\"#ffffff\"/>\n",
+            size
+        ));
+        for pos in Position::all() {
+            let (x, y) = corner(pos);
+            let light = (pos.x + pos.y) % 2 == 1;
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{3}\" height=\"{3}\" fill=\"{}\"/>\n",
+                x,
+                y,
+                if light { LIGHT_SQUARE } else { DARK_SQUARE },
+                SQUARE
+            ));
+        }
+
+        let last_move = self
+            .last_move
+            .filter(|mov| options.highlight && self.is_square(mov.from) && self.is_square(mov.to));
+        let mut highlights: Vec<(Position, &str)> = Vec::new();
+        if let Some(mov) = last_move {
+            highlights.push((mov.from, LAST_MOVE_SQUARE));
+            if mov.to != mov.from {
+                highlights.push((mov.to, LAST_MOVE_SQUARE));
+            }
+        }
+        if options.highlight && self.is_check() {
+            let king = Piece::new(self.turn, King);
+            if let Some(pos) = Position::all().find(|&pos| self.get_square(pos).contains(king)) {
+                highlights.push((pos, CHECK_SQUARE));
+            }
+        }
+        for &(pos, fill) in highlights.iter() {
+            let (x, y) = corner(pos);
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{3}\" height=\"{3}\" fill=\"{}\"/>\n",
+                x, y, fill, SQUARE
+            ));
+        }
+
+        // The filled glyphs for both sides, white ones painted white with a black outline
+        for pos in Position::all() {
+            if let Some(piece) = self.get_piece(pos) {
+                let (x, y) = center(pos);
+                let fill = if piece.color == White {
+                    "#ffffff"
+                } else {
+                    "#000000"
+                };
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" \
+                     dominant-baseline=\"central\" fill=\"{}\" stroke=\"#000000\" \
+                     stroke-width=\"1\">{}</text>\n",
+                    x,
+                    y,
+                    SQUARE * 4 / 5,
+                    fill,
+                    glyph(Some(Piece::new(Black, piece.tipo)), true)
+                ));
+            }
+        }
+
+        if let Some(mov) = last_move.filter(|mov| mov.from != mov.to) {
+            let (x1, y1) = center(mov.from);
+            let (x2, y2) = center(mov.to);
+            svg.push_str(&format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\" \
+                 stroke-linecap=\"round\" marker-end=\"url(#arrowhead)\"/>\n",
+                x1,
+                y1,
+                x2,
+                y2,
+                ARROW,
+                SQUARE / 5
+            ));
+        }
+
+        if options.coordinates {
+            for i in 0..8 {
+                let file = Position::new(Position::ch2x('a') + i as X, Position::ch2y('1'));
+                let rank = Position::new(Position::ch2x('a'), Position::ch2y('1') + i as Y);
+                let (x, _) = center(file);
+                let (_, y) = center(rank);
+                let (file, rank) = (file.to_string().remove(0), rank.to_string().remove(1));
+                for &label_y in [MARGIN / 2, size - MARGIN / 2].iter() {
+                    svg.push_str(&coordinate(x, label_y, file));
+                }
+                for &label_x in [MARGIN / 2, size - MARGIN / 2].iter() {
+                    svg.push_str(&coordinate(label_x, y, rank));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn coordinate(x: i32, y: i32, label: char) -> String {
+    format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"sans-serif\" \
+         text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"#555555\">{}</text>\n",
+        x,
+        y,
+        MARGIN * 3 / 5,
+        label
+    )
+}
+
+pub fn svg_from_fen(fen: &str, options: &RenderOptions) -> Result<String, FenError> {
+    Ok(Game::from_fen(fen)?.svg(options))
+}
+
+#[test]
+fn test_svg() {
+    let options = RenderOptions::default();
+    let mut game = Game::new();
+    let svg = game.svg(&options);
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"400\""));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(svg.matches("<rect").count(), 65);
+    assert_eq!(svg.matches("♟").count(), 16);
+    // Both sides use the filled glyphs
+    assert_eq!(svg.matches("fill=\"#ffffff\" stroke").count(), 16);
+    assert_eq!(svg.matches("font-family").count(), 32);
+    assert!(!svg.contains("<line"));
+
+    game.make_move(&Move::safe_from_string("e2e4")).unwrap();
+    let svg = game.svg(&options);
+    assert_eq!(svg.matches(LAST_MOVE_SQUARE).count(), 2);
+    // From the middle of e2 to the middle of e4
+    assert!(svg.contains("<line x1=\"222\" y1=\"312\" x2=\"222\" y2=\"222\""));
+    let flipped = game.svg(&RenderOptions {
+        orientation: Black,
+        coordinates: false,
+        ..options
+    });
+    assert!(flipped.contains("width=\"360\""));
+    assert!(flipped.contains("<line x1=\"157\" y1=\"67\" x2=\"157\" y2=\"157\""));
+    assert!(!flipped.contains("font-family"));
+
+    let svg = svg_from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1", &options).unwrap();
+    assert_eq!(svg.matches(CHECK_SQUARE).count(), 1);
+    assert!(svg_from_fen("not a fen", &options).is_err());
+}
@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use fen::FenError;
+use game::*;
+use lurri;
+
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub game: Game,
+    pub id: Option<String>,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EpdError {
+    Fen(FenError),
+    BadMove(String, ParseError),
+    UnterminatedString,
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EpdError::Fen(ref e) => write!(f, "{}", e),
+            EpdError::BadMove(ref mov, ref e) => write!(f, "{} ({})", e, mov),
+            EpdError::UnterminatedString => write!(f, "Unterminated string operand"),
+        }
+    }
+}
+
+impl Error for EpdError {}
+
+impl Epd {
+    // The four FEN fields followed by operations like `bm Qxf7+; id "WAC.001";`, operations
+    // other than bm, am, id and c0 are ignored
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        // Fields may be separated by any run of whitespace, the operations are the rest
+        let mut rest = line.trim();
+        let mut fen = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fen.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        let game = Game::from_fen(&fen.join(" ")).map_err(EpdError::Fen)?;
+        let mut epd = Epd {
+            game,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            comment: None,
+        };
+        for operation in split_operations(rest)? {
+            let mut operands = operation.into_iter();
+            let opcode = match operands.next() {
+                Some(opcode) => opcode,
+                None => continue,
+            };
+            match opcode.as_ref() {
+                "bm" => epd.best_moves = epd.parse_moves(operands)?,
+                "am" => epd.avoid_moves = epd.parse_moves(operands)?,
+                "id" => epd.id = operands.next(),
+                "c0" => epd.comment = operands.next(),
+                _ => {}
+            }
+        }
+        Ok(epd)
+    }
+
+    fn parse_moves<I>(&self, operands: I) -> Result<Vec<Move>, EpdError>
+    where
+        I: Iterator<Item = String>,
+    {
+        operands
+            .map(|san| {
+                self.game
+                    .parse_san(&san)
+                    .map_err(|e| EpdError::BadMove(san, e))
+            })
+            .collect()
+    }
+
+    pub fn is_solved_by(&self, mov: &Move) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(mov))
+            && !self.avoid_moves.contains(mov)
+    }
+}
+
+// Splits the operations on semicolons and each one into whitespace separated operands, keeping
+// quoted strings together
+fn split_operations(text: &str) -> Result<Vec<Vec<String>>, EpdError> {
+    let mut operations = Vec::new();
+    let mut operands = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => operations.push(operands.split_off(0)),
+            '"' => {
+                let mut operand = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => operand.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                operands.push(operand);
+            }
+            _ if c.is_whitespace() => {}
+            _ => {
+                let mut operand = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    operand.push(c);
+                    chars.next();
+                }
+                operands.push(operand);
+            }
+        }
+    }
+    if !operands.is_empty() {
+        operations.push(operands);
+    }
+    Ok(operations)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Result<Epd, EpdError>>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(Epd::parse)
+        .collect())
+}
+
+// Searches every position of the suite and prints a line for each one followed by the totals,
+// positions that don't parse count as failed
+pub fn run(engine: &lurri::Engine, suite: &[Result<Epd, EpdError>], limits: lurri::SearchLimits) {
+    let mut solved = 0;
+    let mut total_time = Duration::from_secs(0);
+    for (i, epd) in suite.iter().enumerate() {
+        let epd = match *epd {
+            Ok(ref epd) => epd,
+            Err(ref e) => {
+                println!("{:>4} failed  couldn't parse position, {}", i + 1, e);
+                continue;
+            }
+        };
+        engine.clear();
+        let result = engine.search_limited(&epd.game, limits, |_| {});
+        total_time += result.elapsed;
+        let ok = result.best_move.is_some_and(|mov| epd.is_solved_by(&mov));
+        if ok {
+            solved += 1;
+        }
+        let format_moves = |moves: &[Move]| {
+            let moves: Vec<String> = moves.iter().map(|mov| epd.game.format_san(mov)).collect();
+            moves.join(" ")
+        };
+        let expected = if epd.best_moves.is_empty() {
+            format!("am {}", format_moves(&epd.avoid_moves))
+        } else {
+            format!("bm {}", format_moves(&epd.best_moves))
+        };
+        println!(
+            "{:>4} {:<7} {:<16} played {:<8} {:<16} depth {:>2} {:>6} ms",
+            i + 1,
+            if ok { "solved" } else { "failed" },
+            epd.id.as_ref().map_or("-", |id| id.as_ref()),
+            result
+                .best_move
+                .map_or("none".to_string(), |mov| epd.game.format_san(&mov)),
+            expected,
+            result.depth,
+            result.elapsed.as_millis()
+        );
+    }
+    println!(
+        "Solved {} of {}, failed {}, {} ms",
+        solved,
+        suite.len(),
+        suite.len() - solved,
+        total_time.as_millis()
+    );
+}
+
+#[test]
+fn test_parse_epd() {
+    let epd = Epd::parse(
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"Mate; in 3\";",
+    )
+    .unwrap();
+    assert_eq!(epd.id, Some("WAC.001".to_string()));
+    assert_eq!(epd.comment, Some("Mate; in 3".to_string()));
+    assert_eq!(epd.best_moves, vec![Move::safe_from_string("g3g6")]);
+    assert!(epd.avoid_moves.is_empty());
+    assert!(epd.is_solved_by(&Move::safe_from_string("g3g6")));
+    assert!(!epd.is_solved_by(&Move::safe_from_string("f1f7")));
+
+    let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - am Kd1 Kf1; acd 10;").unwrap();
+    assert_eq!(epd.avoid_moves.len(), 2);
+    assert_eq!(epd.id, None);
+    assert!(epd.is_solved_by(&Move::safe_from_string("e2e4")));
+    assert!(!epd.is_solved_by(&Move::safe_from_string("e1f1")));
+
+    assert_eq!(
+        Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - bm e5;").unwrap_err(),
+        EpdError::BadMove("e5".to_string(), ParseError::IllegalMove)
+    );
+    assert_eq!(
+        Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - id \"open;").unwrap_err(),
+        EpdError::UnterminatedString
+    );
+    assert!(Epd::parse("4k3/8/8/8 w - - bm e4;").is_err());
+
+    let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3  w\t-   -  bm e4; id \"spaced\";").unwrap();
+    assert_eq!(epd.best_moves, vec![Move::safe_from_string("e2e4")]);
+    assert_eq!(epd.id, Some("spaced".to_string()));
+    let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - -").unwrap();
+    assert!(epd.best_moves.is_empty());
+}
+
+#[test]
+fn test_run_epd() {
+    let suite = [
+        Epd::parse("4k3/8/8/8/8/8/3q4/4K3 w - - bm Kxd2; id \"queen\";"),
+        Epd::parse("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id \"backrank\";"),
+    ];
+    for epd in suite.iter() {
+        let epd = epd.as_ref().unwrap();
+        let result =
+            lurri::Engine::new().search_limited(&epd.game, lurri::SearchLimits::depth(3), |_| {});
+        assert!(epd.is_solved_by(&result.best_move.unwrap()));
+    }
+    let result = lurri::Engine::new().search_limited(
+        &Game::new(),
+        lurri::SearchLimits::time(Duration::from_millis(50)),
+        |_| {},
+    );
+    assert!(result.best_move.is_some());
+    assert!(result.elapsed < Duration::from_secs(2));
+}
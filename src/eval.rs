@@ -0,0 +1,211 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use game::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
+use game::*;
+
+pub const PARAM_COUNT: usize = 6;
+
+// Names used for the parameters in files and settings, in the order of EvalParams::values
+pub const PARAM_NAMES: [&str; PARAM_COUNT] =
+    ["pawn", "knight", "bishop", "rook", "queen", "mobility"];
+
+// Weights of the evaluation terms, in the units of the evaluation where a pawn is worth 10.
+// The evaluation is linear in them: the material of each piece type plus the mobility, each
+// counted for white minus for black
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalParams {
+    pub pawn: i32,
+    pub knight: i32,
+    pub bishop: i32,
+    pub rook: i32,
+    pub queen: i32,
+    pub mobility: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        EvalParams {
+            pawn: 10,
+            knight: 30,
+            bishop: 30,
+            rook: 50,
+            queen: 90,
+            mobility: 1,
+        }
+    }
+}
+
+impl EvalParams {
+    pub fn values(&self) -> [i32; PARAM_COUNT] {
+        [
+            self.pawn,
+            self.knight,
+            self.bishop,
+            self.rook,
+            self.queen,
+            self.mobility,
+        ]
+    }
+
+    pub fn from_values(values: [i32; PARAM_COUNT]) -> EvalParams {
+        EvalParams {
+            pawn: values[0],
+            knight: values[1],
+            bishop: values[2],
+            rook: values[3],
+            queen: values[4],
+            mobility: values[5],
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: i32) -> Result<(), String> {
+        let i = PARAM_NAMES
+            .iter()
+            .position(|&n| n == name)
+            .ok_or_else(|| format!("Unknown parameter {}", name))?;
+        let mut values = self.values();
+        values[i] = value;
+        *self = EvalParams::from_values(values);
+        Ok(())
+    }
+
+    pub fn piece_value(&self, tipo: PieceType) -> i32 {
+        match tipo {
+            King => 0,
+            Queen => self.queen,
+            Rook => self.rook,
+            Bishop => self.bishop,
+            Knight => self.knight,
+            Pawn => self.pawn,
+        }
+    }
+
+    // One "name value" pair per line, blank lines and lines starting with # are skipped.
+    // Parameters missing from the text keep their default value
+    pub fn parse(text: &str) -> Result<EvalParams, String> {
+        let mut params = EvalParams::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let value = match fields[..] {
+                [_, value] => value
+                    .parse()
+                    .map_err(|_| format!("Bad value on line {}: {}", i + 1, value))?,
+                _ => return Err(format!("Expected name and value on line {}", i + 1)),
+            };
+            params.set(fields[0], value)?;
+        }
+        Ok(params)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EvalParams, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        EvalParams::parse(&text)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in PARAM_NAMES.iter().zip(self.values().iter()) {
+            writeln!(f, "{} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game {
+    pub fn evaluate_with(&self, params: &EvalParams) -> i32 {
+        self.rules().evaluate(self, params)
+    }
+
+    // Material and mobility, which is all the evaluation of standard chess
+    pub fn standard_evaluation(&self, params: &EvalParams) -> i32 {
+        let mut moves = Vec::with_capacity(80);
+        Position::all().fold(0, |acc, pos| match self.get_piece(pos) {
+            None => acc,
+            Some(piece) => {
+                moves.clear();
+                let mobility = self.get_valid_moves(pos, &mut moves).len() as i32;
+                acc + piece.color.get_sign()
+                    * (params.piece_value(piece.tipo) + params.mobility * mobility)
+            }
+        })
+    }
+
+    // How much each parameter counts in this position, so that the evaluation is the dot
+    // product of these terms and the parameter values
+    pub fn eval_terms(&self) -> [i32; PARAM_COUNT] {
+        let mut terms = [0; PARAM_COUNT];
+        let mut moves = Vec::with_capacity(80);
+        for pos in Position::all() {
+            if let Some(piece) = self.get_piece(pos) {
+                let sign = piece.color.get_sign();
+                let term = match piece.tipo {
+                    King => None,
+                    Pawn => Some(0),
+                    Knight => Some(1),
+                    Bishop => Some(2),
+                    Rook => Some(3),
+                    Queen => Some(4),
+                };
+                if let Some(term) = term {
+                    terms[term] += sign;
+                }
+                moves.clear();
+                terms[5] += sign * self.get_valid_moves(pos, &mut moves).len() as i32;
+            }
+        }
+        terms
+    }
+    // pub fn evaluate_moves<'a>(&self, moves: &'a mut Vec<ValuedMove>) -> &'a mut Vec<ValuedMove> {
+    //     for mov in moves.iter_mut() {
+    //         mov.value = self.evaluate_move(&mov.mov)
+    //     }
+    //     moves
+    // }
+}
+
+#[test]
+fn test_evaluate() {
+    let game: Game = Game::new();
+    assert_eq!(game.evaluate_with(&EvalParams::default()), 0);
+
+    let game =
+        Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+            .unwrap();
+    let params = EvalParams {
+        knight: 31,
+        mobility: 2,
+        ..EvalParams::default()
+    };
+    let terms = game.eval_terms();
+    assert_eq!(terms[..5], [0; 5]);
+    assert!(terms[5] > 0);
+    let dot: i32 = terms
+        .iter()
+        .zip(params.values().iter())
+        .map(|(t, v)| t * v)
+        .sum();
+    assert_eq!(game.evaluate_with(&params), dot);
+}
+
+#[test]
+fn test_eval_params() {
+    let params = EvalParams::parse("# tuned\nqueen 95\n\nmobility 2\n").unwrap();
+    assert_eq!(params.queen, 95);
+    assert_eq!(params.mobility, 2);
+    assert_eq!(params.pawn, 10);
+    assert_eq!(EvalParams::parse(&params.to_string()), Ok(params));
+    assert!(EvalParams::parse("queen").is_err());
+    assert!(EvalParams::parse("queen lots").is_err());
+    assert!(EvalParams::parse("king 1000").is_err());
+}
@@ -0,0 +1,451 @@
+use std::error::Error;
+use std::fmt;
+
+use crazyhouse::{Pocket, POCKET_PIECES};
+use game::Color::{Black, White};
+use game::PieceType::{King, Rook};
+use game::*;
+use variant::{Variant, CHECKS_TO_WIN};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FenError {
+    MissingField,
+    MalformedBoard,
+    UnknownPiece(char),
+    BadTurn,
+    BadCastling,
+    BadEnPassant,
+    BadCounter,
+    BadChecks,
+    IllegalPosition(Vec<SetupError>),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FenError::MissingField => write!(f, "Missing FEN field"),
+            FenError::MalformedBoard => write!(f, "Malformed piece placement"),
+            FenError::UnknownPiece(c) => write!(f, "'{}' is not a valid piece", c),
+            FenError::BadTurn => write!(f, "Bad side to move"),
+            FenError::BadCastling => write!(f, "Bad castling availability"),
+            FenError::BadEnPassant => write!(f, "Bad en passant square"),
+            FenError::BadCounter => write!(f, "Bad move counter"),
+            FenError::BadChecks => write!(f, "Bad remaining checks"),
+            FenError::IllegalPosition(ref problems) => {
+                let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                write!(f, "Illegal position: {}", problems.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for FenError {}
+
+fn fen_char(piece: Piece) -> char {
+    let c = piece.tipo.to_string().chars().next().unwrap();
+    match piece.color {
+        White => c.to_ascii_uppercase(),
+        Black => c,
+    }
+}
+
+impl Game {
+    // The move counters are optional so that the first four fields of an EPD line are enough
+    pub fn from_fen(fen: &str) -> Result<Game, FenError> {
+        Game::from_fen_with_variant(fen, Variant::Standard)
+    }
+
+    // A position of the given variant, unless the FEN has Crazyhouse pockets or Three-check
+    // counts. It must be legal by the rules of the variant it ends up in
+    pub fn from_fen_with_variant(fen: &str, variant: Variant) -> Result<Game, FenError> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or(FenError::MissingField)?;
+
+        let mut game = Game::new();
+        game.clear();
+        game.variant = variant;
+        // Crazyhouse positions end the placement with the pockets, as in [Qp]
+        let placement = match placement.find('[') {
+            Some(start) if placement.ends_with(']') => {
+                game.pockets = parse_pockets(&placement[start + 1..placement.len() - 1])?;
+                game.variant = Variant::Crazyhouse;
+                &placement[..start]
+            }
+            Some(_) => return Err(FenError::MalformedBoard),
+            None => placement,
+        };
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::MalformedBoard);
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let y = Position::ch2y('8') - i as Y;
+            let mut x = Position::ch2x('a');
+            for c in rank.chars() {
+                if let Some(n) = c.to_digit(10) {
+                    x += n as X;
+                    if x > Position::ch2x('h') + 1 {
+                        return Err(FenError::MalformedBoard);
+                    }
+                    continue;
+                }
+                // Promoted pieces are marked in Crazyhouse, they go back to pawns when captured
+                if c == '~' {
+                    let pos = Position::new(x - 1, y);
+                    if pos.x < Position::ch2x('a') || game.get_piece(pos).is_none() {
+                        return Err(FenError::MalformedBoard);
+                    }
+                    game.set_promoted(pos, true);
+                    continue;
+                }
+                if x > Position::ch2x('h') {
+                    return Err(FenError::MalformedBoard);
+                }
+                let tipo = PieceType::from_char(c.to_ascii_lowercase())
+                    .map_err(|_| FenError::UnknownPiece(c))?;
+                let color = if c.is_ascii_uppercase() { White } else { Black };
+                game.set_square(Position::new(x, y), Some(Piece::new(color, tipo)))
+                    .map_err(|_| FenError::MalformedBoard)?;
+                x += 1;
+            }
+            if x != Position::ch2x('h') + 1 {
+                return Err(FenError::MalformedBoard);
+            }
+        }
+
+        game.turn = match fields.next() {
+            Some("w") => White,
+            Some("b") => Black,
+            Some(_) => return Err(FenError::BadTurn),
+            None => return Err(FenError::MissingField),
+        };
+
+        game.castling = match fields.next() {
+            Some("-") => CastlingRights::none(),
+            Some(castling) => parse_castling(&game, castling)?,
+            None => return Err(FenError::MissingField),
+        };
+        game.chess960 = !game.castling.is_classical();
+
+        game.en_passant = match fields.next() {
+            Some("-") => None,
+            Some(square) => {
+                let chars: Vec<char> = square.chars().collect();
+                if chars.len() != 2 {
+                    return Err(FenError::BadEnPassant);
+                }
+                Some(Position::from_chars(chars[0], chars[1]).map_err(|_| FenError::BadEnPassant)?)
+            }
+            None => return Err(FenError::MissingField),
+        };
+
+        // Three-check positions carry the checks each side still needs to win, as in 3+2
+        let mut fields = fields.peekable();
+        if let Some(checks) = fields.peek().and_then(|field| parse_checks(field)) {
+            game.checks = checks?;
+            game.variant = Variant::ThreeCheck;
+            fields.next();
+        }
+
+        if let Some(halfmove) = fields.next() {
+            game.halfmove_clock = halfmove.parse().map_err(|_| FenError::BadCounter)?;
+        }
+        if let Some(fullmove) = fields.next() {
+            game.fullmove_number = fullmove.parse().map_err(|_| FenError::BadCounter)?;
+        }
+
+        let problems = game.validate();
+        if !problems.is_empty() {
+            return Err(FenError::IllegalPosition(problems));
+        }
+        Ok(game)
+    }
+
+    // X-FEN, which for classical castling rights is the same as standard FEN
+    pub fn to_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    // Shredder-FEN, where castling rights are always written as the files of the rooks
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(&self, shredder: bool) -> String {
+        let mut fen = String::new();
+        for y in (Position::ch2y('1')..=Position::ch2y('8')).rev() {
+            let mut empty = 0;
+            for x in Position::ch2x('a')..=Position::ch2x('h') {
+                match self.get_piece(Position::new(x, y)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(fen_char(piece));
+                        if self.is_promoted(Position::new(x, y)) {
+                            fen.push('~');
+                        }
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if y != Position::ch2y('1') {
+                fen.push('/');
+            }
+        }
+
+        if self.variant == Variant::Crazyhouse {
+            fen.push('[');
+            for &color in [White, Black].iter() {
+                for tipo in self.pockets[color.index()].pieces() {
+                    fen.push(fen_char(Piece::new(color, tipo)));
+                }
+            }
+            fen.push(']');
+        }
+
+        fen.push_str(match self.turn {
+            White => " w ",
+            Black => " b ",
+        });
+
+        fen.push_str(&self.castling_field(shredder));
+
+        match self.en_passant {
+            Some(pos) => fen.push_str(&format!(" {}", pos)),
+            None => fen.push_str(" -"),
+        }
+        if self.variant == Variant::ThreeCheck {
+            fen.push_str(&format!(
+                " {}+{}",
+                CHECKS_TO_WIN.saturating_sub(self.checks[0]),
+                CHECKS_TO_WIN.saturating_sub(self.checks[1])
+            ));
+        }
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+
+    // KQkq name the outermost rooks, other rooks are named by their file
+    fn castling_field(&self, shredder: bool) -> String {
+        let castling = self.castling;
+        let mut field = String::new();
+        for &(color, short) in [(White, true), (White, false), (Black, true), (Black, false)].iter()
+        {
+            let right = if short {
+                castling.short(color)
+            } else {
+                castling.long(color)
+            };
+            if !right {
+                continue;
+            }
+            let file = castling.rook_file(short);
+            let c = if !shredder && outermost_rook(self, color, short) == Some(file) {
+                if short {
+                    'k'
+                } else {
+                    'q'
+                }
+            } else {
+                (b'a' + (file - Position::ch2x('a')) as u8) as char
+            };
+            field.push(match color {
+                White => c.to_ascii_uppercase(),
+                Black => c,
+            });
+        }
+        if field.is_empty() {
+            field.push('-');
+        }
+        field
+    }
+}
+
+fn king_file(game: &Game, color: Color) -> Option<X> {
+    let rank = Game::back_rank(color);
+    (Position::ch2x('a')..=Position::ch2x('h'))
+        .find(|&x| game.get_piece(Position::new(x, rank)) == Some(Piece::new(color, King)))
+}
+
+// The file of the rook furthest from the king on its side
+fn outermost_rook(game: &Game, color: Color, short: bool) -> Option<X> {
+    let rank = Game::back_rank(color);
+    let king = king_file(game, color)?;
+    let is_rook = |&x: &X| game.get_piece(Position::new(x, rank)) == Some(Piece::new(color, Rook));
+    if short {
+        (king + 1..=Position::ch2x('h')).rev().find(is_rook)
+    } else {
+        (Position::ch2x('a')..king).find(is_rook)
+    }
+}
+
+// Reads standard FEN, X-FEN and Shredder-FEN castling rights. Both colours must castle with
+// the same files. A right without its rook is kept, so that validate reports it
+fn parse_castling(game: &Game, field: &str) -> Result<CastlingRights, FenError> {
+    let mut castling = CastlingRights::none();
+    let (mut king, mut short_rook, mut long_rook) = (None, None, None);
+    let agree = |slot: &mut Option<X>, x: X| match *slot {
+        Some(other) if other != x => Err(FenError::BadCastling),
+        _ => {
+            *slot = Some(x);
+            Ok(())
+        }
+    };
+    for c in field.chars() {
+        let color = if c.is_ascii_uppercase() { White } else { Black };
+        let king_x = king_file(game, color);
+        let (short, file) = match c.to_ascii_lowercase() {
+            'k' => (
+                true,
+                outermost_rook(game, color, true).unwrap_or_else(|| Position::ch2x('h')),
+            ),
+            'q' => (
+                false,
+                outermost_rook(game, color, false).unwrap_or_else(|| Position::ch2x('a')),
+            ),
+            file @ 'a'..='h' => {
+                let x = Position::ch2x(file);
+                (x > king_x.ok_or(FenError::BadCastling)?, x)
+            }
+            _ => return Err(FenError::BadCastling),
+        };
+        if let Some(x) = king_x {
+            agree(&mut king, x)?;
+        }
+        agree(
+            if short {
+                &mut short_rook
+            } else {
+                &mut long_rook
+            },
+            file,
+        )?;
+        castling.set(color, short);
+    }
+    castling.king_file = king.unwrap_or(castling.king_file);
+    castling.short_rook_file = short_rook.unwrap_or(castling.short_rook_file);
+    castling.long_rook_file = long_rook.unwrap_or(castling.long_rook_file);
+    Ok(castling)
+}
+
+fn parse_pockets(field: &str) -> Result<[Pocket; 2], FenError> {
+    let mut pockets = [Pocket::default(); 2];
+    for c in field.chars() {
+        let tipo = PieceType::from_char(c.to_ascii_lowercase())
+            .ok()
+            .filter(|tipo| POCKET_PIECES.contains(tipo))
+            .ok_or(FenError::UnknownPiece(c))?;
+        let color = if c.is_ascii_uppercase() { White } else { Black };
+        pockets[color.index()].add(tipo);
+    }
+    Ok(pockets)
+}
+
+// None if the field isn't a checks field at all, as the move counters aren't
+fn parse_checks(field: &str) -> Option<Result<[u32; 2], FenError>> {
+    let split = field.find('+')?;
+    let remaining = |count: &str| match count.parse::<u32>() {
+        Ok(n) if n <= CHECKS_TO_WIN => Ok(CHECKS_TO_WIN - n),
+        _ => Err(FenError::BadChecks),
+    };
+    Some(
+        remaining(&field[..split])
+            .and_then(|white| remaining(&field[split + 1..]).map(|black| [white, black])),
+    )
+}
+
+#[test]
+fn test_fen_roundtrip() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(Game::new().to_fen(), fen);
+    assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+
+    let mut game = Game::new();
+    game.make_move(&Move::safe_from_string("e2e4")).unwrap();
+    let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+    assert_eq!(game.to_fen(), fen);
+    assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+
+    let fen = "4k3/8/8/8/8/8/8/4K2R w K - 3 40";
+    assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+
+    let fen = "4k3/8/8/8/8/8/8/4K2R w K - 1+3 3 40";
+    let game = Game::from_fen(fen).unwrap();
+    assert_eq!(game.variant, Variant::ThreeCheck);
+    assert_eq!(game.checks, [2, 0]);
+    assert_eq!(game.to_fen(), fen);
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 4+3 3 40").unwrap_err(),
+        FenError::BadChecks
+    );
+}
+
+#[test]
+fn test_fen_errors() {
+    assert_eq!(Game::from_fen("").unwrap_err(), FenError::MissingField);
+    assert_eq!(
+        Game::from_fen("8/8/8/8/8/8/8 w - -").unwrap_err(),
+        FenError::MalformedBoard
+    );
+    assert_eq!(
+        Game::from_fen("9999999999999999/8/8/8/8/8/8/8 w - -").unwrap_err(),
+        FenError::MalformedBoard
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4K3R w - -").unwrap_err(),
+        FenError::MalformedBoard
+    );
+    // The promoted mark of Crazyhouse goes after a piece
+    assert_eq!(
+        Game::from_fen("~7/8/8/8/8/8/8/8 w - -").unwrap_err(),
+        FenError::MalformedBoard
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4K1~2 w - -").unwrap_err(),
+        FenError::MalformedBoard
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4K3 x - -").unwrap_err(),
+        FenError::BadTurn
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4J3 w - -").unwrap_err(),
+        FenError::UnknownPiece('J')
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4K3 w K -").unwrap_err(),
+        FenError::IllegalPosition(vec![SetupError::BadCastlingRights(White)])
+    );
+    assert_eq!(
+        Game::from_fen("r3k2r/8/8/8/8/8/8/R2K3R w KQkq -").unwrap_err(),
+        FenError::BadCastling
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/4K3 w X -").unwrap_err(),
+        FenError::BadCastling
+    );
+    assert_eq!(
+        Game::from_fen("4k3/8/8/8/8/8/8/8 w - -").unwrap_err(),
+        FenError::IllegalPosition(vec![SetupError::MissingKing(White)])
+    );
+    // Antichess has no royal king
+    let game = Game::from_fen_with_variant("8/8/8/8/8/8/P7/8 w - - 0 1", Variant::Antichess);
+    assert_eq!(game.unwrap().variant, Variant::Antichess);
+    assert!(Game::from_fen_with_variant("kk6/8/8/8/8/8/8/KR6 b - -", Variant::Antichess).is_ok());
+    assert_eq!(
+        Game::from_fen_with_variant("8/8/8/8/8/8/P7/8 w - - 0 1", Variant::KingOfTheHill)
+            .unwrap_err(),
+        FenError::IllegalPosition(vec![
+            SetupError::MissingKing(White),
+            SetupError::MissingKing(Black)
+        ])
+    );
+}
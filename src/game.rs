@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use std::error::Error;
 use std::fmt;
 
 use std::cmp::{Eq, Ord, Ordering, PartialOrd};
@@ -7,6 +8,11 @@ use std::ops::{Add, Neg, Not};
 
 extern crate bit_vec;
 
+use crazyhouse::Pocket;
+use eval::EvalParams;
+use render::RenderOptions;
+use variant::Variant;
+
 const BASE_MOVEMENT_CAPACITY: usize = 100;
 
 const KING_DELTAS: &'static [PositionDelta; 8] = &[
@@ -50,6 +56,20 @@ pub type Board = [[Option<Square>; 12]; 12];
 pub struct Game {
     pub board: Board,
     pub turn: Color,
+    pub castling: CastlingRights,
+    pub en_passant: Option<Position>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    // Castling moves are written as the king taking its rook, and FENs use X-FEN
+    pub chess960: bool,
+    pub variant: Variant,
+    // Checks given by white and by black, only counted in Three-check
+    pub checks: [u32; 2],
+    // Pieces captured by white and by black, only used in Crazyhouse
+    pub pockets: [Pocket; 2],
+    // Squares of the pieces that were pawns before promoting, by Position::index
+    pub promoted: u64,
+    pub last_move: Option<Move>,
 }
 
 impl Game {
@@ -190,11 +210,26 @@ impl Game {
                 ],
             ],
             turn: White,
+            castling: CastlingRights::all(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            chess960: false,
+            variant: Variant::Standard,
+            checks: [0, 0],
+            pockets: [Pocket::default(); 2],
+            promoted: 0,
+            last_move: None,
         }
     }
     pub fn show(&self) {
         println!("{}", self)
     }
+    pub fn clear(&mut self) {
+        for pos in Position::all() {
+            self.board[pos.y as usize][pos.x as usize] = Some(Square::empty());
+        }
+    }
     pub fn get_raw_square(&self, pos: Position) -> Option<Square> {
         self.board[pos.y as usize][pos.x as usize]
     }
@@ -208,10 +243,10 @@ impl Game {
         &mut self,
         pos: Position,
         square: Option<Square>,
-    ) -> Result<(), &'static str> {
+    ) -> Result<(), MoveError> {
         Ok(self.board[pos.y as usize][pos.x as usize] = square)
     }
-    pub fn set_square(&mut self, pos: Position, piece: Option<Piece>) -> Result<(), &'static str> {
+    pub fn set_square(&mut self, pos: Position, piece: Option<Piece>) -> Result<(), MoveError> {
         self.set_raw_square(pos, Some(Square::new(piece)))
     }
     pub fn is_square(&self, pos: Position) -> bool {
@@ -230,7 +265,58 @@ impl Game {
             }
         }
     }
-    pub fn make_move(&mut self, m: &Move) -> Result<(), &'static str> {
+    pub fn make_move(&mut self, m: &Move) -> Result<(), MoveError> {
+        if self.rules().forced_captures()
+            && !self.is_capture(m)
+            && self
+                .get_all_valid_moves()
+                .iter()
+                .any(|other| self.is_capture(&other.mov))
+        {
+            return Err(MoveError::MustCapture);
+        }
+        let color = self.turn;
+        let moved = self
+            .get_raw_square(m.from)
+            .and_then(|square| square.content);
+        let captured = match (m.tipo, moved) {
+            (MoveType::ShortCastling, _) | (MoveType::LongCastling, _) => None,
+            (_, Some(piece!(_, Pawn))) if self.en_passant == Some(m.to) && m.from.x != m.to.x => {
+                Some(Piece::new(!color, Pawn))
+            }
+            _ => self.get_raw_square(m.to).and_then(|square| square.content),
+        };
+        self.move_pieces(m)?;
+
+        self.update_castling_rights(m, color, moved);
+        self.en_passant = match moved {
+            Some(piece!(_, Pawn)) if (m.to.y - m.from.y).abs() == 2 => {
+                Some(Position::new(m.from.x, (m.from.y + m.to.y) / 2))
+            }
+            _ => None,
+        };
+        match (moved, captured) {
+            (Some(piece!(_, Pawn)), _) | (_, Some(_)) => self.halfmove_clock = 0,
+            _ => self.halfmove_clock += 1,
+        }
+        if color == Black {
+            self.fullmove_number += 1;
+        }
+        self.rules().after_move(self, m, captured);
+        self.last_move = Some(*m);
+        Ok(())
+    }
+    fn update_castling_rights(&mut self, m: &Move, color: Color, moved: Option<Piece>) {
+        match (m.tipo, moved) {
+            (MoveType::LongCastling, _)
+            | (MoveType::ShortCastling, _)
+            | (_, Some(piece!(_, King))) => self.castling.remove(color),
+            _ => {}
+        }
+        self.castling.remove_rook(m.from);
+        self.castling.remove_rook(m.to);
+    }
+    fn move_pieces(&mut self, m: &Move) -> Result<(), MoveError> {
         match m.tipo {
             MoveType::Normal => {
                 self.is_valid_normal_move(m)?;
@@ -244,120 +330,147 @@ impl Game {
                 self.set_square(m.to, Some(Piece::new(color, pt)))?;
                 Ok(())
             }
-            MoveType::LongCastling => match self.turn {
-                White => {
-                    let king_mov = Move::new(
-                        Position::safe_from_chars('e', '1'),
-                        Position::safe_from_chars('c', '1'),
-                        MoveType::Normal,
-                    );
-                    let rook_mov = Move::new(
-                        Position::safe_from_chars('a', '1'),
-                        Position::safe_from_chars('d', '1'),
-                        MoveType::Normal,
-                    );
-                    if self.get_square(rook_mov.from) == Square::white_rook()
-                        && self.get_square(king_mov.from) == Square::white_king()
-                        && self.get_to_by(
-                            &Move::new(king_mov.from, rook_mov.from, MoveType::Normal),
-                            Left,
-                        )
-                    {
-                        self.raw_move(&rook_mov)?;
-                        self.raw_make_move(&king_mov)?;
-                        Ok(())
-                    } else {
-                        Err("Bad long casting")
-                    }
-                }
-                Black => {
-                    let king_mov = Move::new(
-                        Position::safe_from_chars('e', '8'),
-                        Position::safe_from_chars('c', '8'),
-                        MoveType::Normal,
-                    );
-                    let rook_mov = Move::new(
-                        Position::safe_from_chars('a', '8'),
-                        Position::safe_from_chars('d', '8'),
-                        MoveType::Normal,
-                    );
-                    if self.get_square(rook_mov.from) == Square::white_rook()
-                        && self.get_square(king_mov.from) == Square::white_king()
-                        && self.get_to_by(
-                            &Move::new(king_mov.from, rook_mov.from, MoveType::Normal),
-                            Left,
-                        )
-                    {
-                        self.raw_move(&rook_mov)?;
-                        self.raw_make_move(&king_mov)?;
-                        Ok(())
-                    } else {
-                        Err("Bad long casting")
-                    }
-                }
-            },
-            MoveType::ShortCastling => match self.turn {
-                White => {
-                    let king_mov = Move::new(
-                        Position::safe_from_chars('e', '1'),
-                        Position::safe_from_chars('g', '1'),
-                        MoveType::Normal,
-                    );
-                    let rook_mov = Move::new(
-                        Position::safe_from_chars('h', '1'),
-                        Position::safe_from_chars('f', '1'),
-                        MoveType::Normal,
-                    );
-                    if self.get_square(rook_mov.from) == Square::white_rook()
-                        && self.get_square(king_mov.from) == Square::white_king()
-                        && self.get_to_by(
-                            &Move::new(king_mov.from, rook_mov.from, MoveType::Normal),
-                            Right,
-                        )
-                    {
-                        self.raw_move(&rook_mov)?;
-                        self.raw_make_move(&king_mov)?;
-                        Ok(())
-                    } else {
-                        Err("Bad short casting")
-                    }
-                }
-                Black => {
-                    let king_mov = Move::new(
-                        Position::safe_from_chars('e', '8'),
-                        Position::safe_from_chars('g', '8'),
-                        MoveType::Normal,
-                    );
-                    let rook_mov = Move::new(
-                        Position::safe_from_chars('h', '8'),
-                        Position::safe_from_chars('f', '8'),
-                        MoveType::Normal,
-                    );
-                    if self.get_square(rook_mov.from) == Square::black_rook()
-                        && self.get_square(king_mov.from) == Square::black_king()
-                        && self.get_to_by(
-                            &Move::new(king_mov.from, rook_mov.from, MoveType::Normal),
-                            Right,
-                        )
-                    {
-                        self.raw_move(&rook_mov)?;
-                        self.raw_make_move(&king_mov)?;
-                        Ok(())
-                    } else {
-                        Err("Bad short casting")
-                    }
+            MoveType::ShortCastling => self.castle(true),
+            MoveType::LongCastling => self.castle(false),
+            MoveType::Drop(pt) => self.drop_piece(pt, m.to),
+        }
+    }
+    // The king always ends on the g or c file and the rook next to it, on the f or d file, as
+    // in Chess960. Every square they go through must be empty except for the two of them, and
+    // the king can't leave, cross or land on an attacked square
+    fn castle(&mut self, short: bool) -> Result<(), MoveError> {
+        let color = self.turn;
+        let error = if short {
+            MoveError::BadShortCastling
+        } else {
+            MoveError::BadLongCastling
+        };
+        let rank = Game::back_rank(color);
+        let king_from = self.king_home(color);
+        let rook_from = Position::new(self.castling.rook_file(short), rank);
+        let (king_to, rook_to) = if short {
+            (
+                Position::new(Position::ch2x('g'), rank),
+                Position::new(Position::ch2x('f'), rank),
+            )
+        } else {
+            (
+                Position::new(Position::ch2x('c'), rank),
+                Position::new(Position::ch2x('d'), rank),
+            )
+        };
+        let king = Piece::new(color, King);
+        let rook = Piece::new(color, Rook);
+        let allowed = if short {
+            self.castling.short(color)
+        } else {
+            self.castling.long(color)
+        };
+        if !allowed
+            || self.get_piece(king_from) != Some(king)
+            || self.get_piece(rook_from) != Some(rook)
+        {
+            return Err(error);
+        }
+        let xs = [king_from.x, king_to.x, rook_from.x, rook_to.x];
+        let (min, max) = (*xs.iter().min().unwrap(), *xs.iter().max().unwrap());
+        for x in min..=max {
+            let pos = Position::new(x, rank);
+            if pos != king_from && pos != rook_from && self.get_piece(pos).is_some() {
+                return Err(error);
+            }
+        }
+        let (from, to) = (king_from.x.min(king_to.x), king_from.x.max(king_to.x));
+        if (from..=to).any(|x| self.can_be_eaten_by(Position::new(x, rank), !color)) {
+            return Err(error);
+        }
+        let mut aux = self.clone();
+        aux.set_square(king_from, None)?;
+        aux.set_square(rook_from, None)?;
+        aux.set_square(king_to, Some(king))?;
+        aux.set_square(rook_to, Some(rook))?;
+        aux.turn = !color;
+        if !self.rules().is_legal(&aux) {
+            return Err(error);
+        }
+        self.board = aux.board;
+        self.turn = aux.turn;
+        Ok(())
+    }
+    // Reads coordinate notation, where castling can also be written as the king's move (e1g1),
+    // or in Chess960 as the king taking its own rook (e1h1)
+    pub fn parse_move(&self, s: &str) -> Result<Move, ParseError> {
+        let mov = Move::from_string(s)?;
+        if mov.tipo == MoveType::Normal && mov.from.y == mov.to.y {
+            if let Some(piece!(color, King)) = self.get_piece(mov.from) {
+                let home = self.king_home(color);
+                let takes_rook = self.get_piece(mov.to) == Some(Piece::new(color, Rook));
+                let (short, long) = if self.chess960 {
+                    (
+                        takes_rook && mov.to.x == self.castling.short_rook_file,
+                        takes_rook && mov.to.x == self.castling.long_rook_file,
+                    )
+                } else {
+                    (mov.to == home.right().right(), mov.to == home.left().left())
+                };
+                if mov.from == home && color == self.turn && (short || long) {
+                    return Ok(self.castling_move(short));
                 }
-            },
+            }
+        }
+        Ok(mov)
+    }
+    // Writes coordinate notation, with castling written as the king's move, or in Chess960 as
+    // the king taking its own rook
+    pub fn format_move(&self, mov: &Move) -> String {
+        let home = self.king_home(self.turn);
+        match mov.tipo {
+            MoveType::ShortCastling | MoveType::LongCastling => {
+                let short = mov.tipo == MoveType::ShortCastling;
+                let to = if self.chess960 {
+                    Position::new(self.castling.rook_file(short), home.y)
+                } else {
+                    self.castling_move(short).to
+                };
+                format!("{}{}", home, to)
+            }
+            _ => mov.to_string(),
+        }
+    }
+    pub fn back_rank(color: Color) -> Y {
+        match color {
+            White => Position::ch2y('1'),
+            Black => Position::ch2y('8'),
+        }
+    }
+    fn king_home(&self, color: Color) -> Position {
+        Position::new(self.castling.king_file, Game::back_rank(color))
+    }
+    // Castling for the side to move, from the king's square to where the king ends up
+    pub fn castling_move(&self, short: bool) -> Move {
+        let home = self.king_home(self.turn);
+        if short {
+            Move::new(
+                home,
+                Position::new(Position::ch2x('g'), home.y),
+                MoveType::ShortCastling,
+            )
+        } else {
+            Move::new(
+                home,
+                Position::new(Position::ch2x('c'), home.y),
+                MoveType::LongCastling,
+            )
         }
     }
-    pub fn is_valid_normal_move(&self, m: &Move) -> Result<(), &'static str> {
+    pub fn is_valid_normal_move(&self, m: &Move) -> Result<(), MoveError> {
         match (self.get_raw_square(m.from), self.get_raw_square(m.to)) {
-            (None, _) => Err("Not even a valid square"),
-            (_, None) => Err("Not even a valid square"),
-            (Some(Square { content: None }), _) => Err("Empty from square"),
+            (None, _) => Err(MoveError::NotASquare),
+            (_, None) => Err(MoveError::NotASquare),
+            (Some(Square { content: None }), _) => Err(MoveError::EmptySquare),
             (Some(from_square), Some(to_square)) => {
                 if from_square.get_piece().color != self.turn {
-                    return Err("Wrong color");
+                    return Err(MoveError::WrongColor);
                 };
                 match from_square.get_piece() {
                     piece!(color, King) => {
@@ -373,7 +486,7 @@ impl Game {
                         {
                             Ok(())
                         } else {
-                            Err("Bad King movement")
+                            Err(MoveError::BadMovement(King))
                         }
                     }
                     piece!(color, Queen) => {
@@ -389,7 +502,7 @@ impl Game {
                         {
                             Ok(())
                         } else {
-                            Err("Bad Queen movement")
+                            Err(MoveError::BadMovement(Queen))
                         }
                     }
                     piece!(color, Rook) => {
@@ -401,7 +514,7 @@ impl Game {
                         {
                             Ok(())
                         } else {
-                            Err("Bad Rook movement")
+                            Err(MoveError::BadMovement(Rook))
                         }
                     }
                     piece!(color, Bishop) => {
@@ -413,7 +526,7 @@ impl Game {
                         {
                             Ok(())
                         } else {
-                            Err("Bad Bishop movement")
+                            Err(MoveError::BadMovement(Bishop))
                         }
                     }
                     piece!(color, Knight) => {
@@ -429,7 +542,7 @@ impl Game {
                         {
                             Ok(())
                         } else {
-                            Err("Bad Knight movement")
+                            Err(MoveError::BadMovement(Knight))
                         }
                     }
                     piece!(color, Pawn) => {
@@ -438,7 +551,7 @@ impl Game {
                             Black => (Position::ch2y('2'), Position::ch2y('7'), Down),
                         };
                         if m.from.y == promotion_y && !m.is_promotion() {
-                            Err("You must promote that pawn")
+                            Err(MoveError::MustPromote)
                         } else if (m.from.y == long_move_y
                             && m.from.go(foward_dir).go(foward_dir) == m.to
                             && to_square.has_none()
@@ -446,35 +559,60 @@ impl Game {
                             || (m.from.go(foward_dir) == m.to && to_square.has_none())
                             || ((m.from.go(foward_dir).left() == m.to
                                 || m.from.go(foward_dir).right() == m.to)
-                                && to_square.has_color(!color))
+                                && (to_square.has_color(!color) || self.en_passant == Some(m.to)))
                         {
                             Ok(())
                         } else {
-                            Err("Bad pawn movement")
+                            Err(MoveError::BadMovement(Pawn))
                         }
                     }
                 }
             }
         }
     }
-    pub fn raw_make_move(&mut self, m: &Move) -> Result<(), &'static str> {
+    pub fn raw_make_move(&mut self, m: &Move) -> Result<(), MoveError> {
         let mut aux = self.clone();
         aux.raw_move(m)?;
         aux.turn = !aux.turn;
-        if aux.can_eat_king() {
-            return Err("The king can be eaten after that move");
+        if !self.rules().is_legal(&aux) {
+            return Err(MoveError::KingInCheck);
         }
         self.raw_move(m)?;
         self.turn = !self.turn;
         Ok(())
     }
-    pub fn raw_move(&mut self, m: &Move) -> Result<(), &'static str> {
+    pub fn raw_move(&mut self, m: &Move) -> Result<(), MoveError> {
         let from_piece = self.get_piece(m.from);
+        if let Some(piece!(_, Pawn)) = from_piece {
+            if self.en_passant == Some(m.to) && m.from.x != m.to.x {
+                self.set_square(Position::new(m.to.x, m.from.y), None)?;
+            }
+        }
         self.set_square(m.to, from_piece)?;
         self.set_square(m.from, None)?;
         Ok(())
     }
+    // Every legal move, castling included
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = self.candidate_moves();
+        moves.retain(|mov| self.clone().make_move(mov).is_ok());
+        moves
+    }
+    // The generated moves plus castling, make_move still rejects the illegal ones
+    pub fn candidate_moves(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = self.get_all_valid_moves().iter().map(|m| m.mov).collect();
+        if self.castling.any() {
+            moves.push(self.castling_move(true));
+            moves.push(self.castling_move(false));
+        }
+        moves
+    }
     pub fn get_all_valid_moves(&self) -> Vec<ValuedMove> {
+        self.rules().generate_moves(self)
+    }
+    // Every move the pieces can make by the rules of standard chess, castling aside, without
+    // looking at whether the king is left in check
+    pub fn standard_moves(&self) -> Vec<ValuedMove> {
         let mut moves = Vec::with_capacity(BASE_MOVEMENT_CAPACITY);
         for from_pos in Position::all() {
             if self.get_square(from_pos).has_color(self.turn) {
@@ -555,7 +693,7 @@ impl Game {
                 .iter()
                 {
                     if let Some(to_square) = self.get_raw_square(*to_pos) {
-                        if to_square.has_color(!color) {
+                        if to_square.has_color(!color) || self.en_passant == Some(*to_pos) {
                             if from_pos.y == promotion_y {
                                 for promotion_piece in [Queen, Rook, Bishop, Knight].iter() {
                                     moves.push(ValuedMove::new(
@@ -597,56 +735,203 @@ impl Game {
         moves
     }
     pub fn can_be_eaten_by(&self, from_pos: Position, color: Color) -> bool {
-        for &pt in [King, Queen, Rook, Bishop, Knight, Pawn].iter() {
-            match pt {
-                King | Knight => {
-                    for delta_pos in pt.get_posible_deltas().iter() {
-                        let to_pos = from_pos + *delta_pos;
-                        if let Some(to_square) = self.get_raw_square(to_pos) {
-                            if to_square.contains(Piece::new(color, pt)) {
-                                return true;
-                            }
+        [King, Queen, Rook, Bishop, Knight, Pawn]
+            .iter()
+            .any(|&pt| self.attacker(from_pos, color, pt).is_some())
+    }
+    // The cheapest piece of color attacking from_pos, with where it stands
+    pub fn least_valuable_attacker(
+        &self,
+        from_pos: Position,
+        color: Color,
+    ) -> Option<(Position, PieceType)> {
+        [Pawn, Knight, Bishop, Rook, Queen, King]
+            .iter()
+            .filter_map(|&pt| self.attacker(from_pos, color, pt).map(|pos| (pos, pt)))
+            .next()
+    }
+    fn attacker(&self, from_pos: Position, color: Color, pt: PieceType) -> Option<Position> {
+        match pt {
+            King | Knight => {
+                for delta_pos in pt.get_posible_deltas().iter() {
+                    let to_pos = from_pos + *delta_pos;
+                    if let Some(to_square) = self.get_raw_square(to_pos) {
+                        if to_square.contains(Piece::new(color, pt)) {
+                            return Some(to_pos);
                         }
                     }
                 }
-                Rook | Bishop | Queen => {
-                    for dir in pt.get_posible_dirs().iter() {
-                        for to_pos in from_pos.iter_to(*dir) {
-                            if let Some(to_square) = self.get_raw_square(to_pos) {
-                                if to_square.contains(Piece::new(color, pt)) {
-                                    return true;
-                                } else if to_square.has_none() {
-                                    continue;
-                                } else {
-                                    break;
-                                }
+            }
+            Rook | Bishop | Queen => {
+                for dir in pt.get_posible_dirs().iter() {
+                    for to_pos in from_pos.iter_to(*dir) {
+                        if let Some(to_square) = self.get_raw_square(to_pos) {
+                            if to_square.contains(Piece::new(color, pt)) {
+                                return Some(to_pos);
+                            } else if to_square.has_none() {
+                                continue;
                             } else {
                                 break;
                             }
+                        } else {
+                            break;
                         }
                     }
                 }
-                Pawn => {
-                    let backward_dir = match color {
-                        White => Down,
-                        Black => Up,
-                    };
-                    for &to_pos in [
-                        from_pos.go(backward_dir).left(),
-                        from_pos.go(backward_dir).right(),
-                    ]
-                    .iter()
-                    {
-                        if let Some(to_square) = self.get_raw_square(to_pos) {
-                            if to_square.contains(Piece::new(color, pt)) {
-                                return true;
-                            }
+            }
+            Pawn => {
+                let backward_dir = match color {
+                    White => Down,
+                    Black => Up,
+                };
+                for &to_pos in [
+                    from_pos.go(backward_dir).left(),
+                    from_pos.go(backward_dir).right(),
+                ]
+                .iter()
+                {
+                    if let Some(to_square) = self.get_raw_square(to_pos) {
+                        if to_square.contains(Piece::new(color, pt)) {
+                            return Some(to_pos);
                         }
                     }
                 }
             }
         }
-        false
+        None
+    }
+    pub fn is_capture(&self, mov: &Move) -> bool {
+        match self.get_piece(mov.from) {
+            Some(piece!(_, Pawn)) if self.en_passant == Some(mov.to) => true,
+            _ => {
+                mov.tipo != MoveType::ShortCastling
+                    && mov.tipo != MoveType::LongCastling
+                    && self.get_piece(mov.to).is_some()
+            }
+        }
+    }
+    // Static exchange evaluation: what the side to move wins on the destination square when
+    // both sides keep recapturing there with their cheapest piece, and either side can stop
+    // when going on would lose material. Scored with the piece values of params
+    pub fn see(&self, mov: &Move, params: &EvalParams) -> i32 {
+        let see_value = |pt: PieceType| match pt {
+            King => 1000,
+            _ => params.piece_value(pt),
+        };
+        let mut board = self.clone();
+        let target = mov.to;
+        let mut gains = vec![match self.get_piece(target) {
+            Some(piece) => see_value(piece.tipo),
+            None if self.is_capture(mov) => {
+                let captured = Position::new(target.x, mov.from.y);
+                let _ = board.set_square(captured, None);
+                see_value(Pawn)
+            }
+            None => 0,
+        }];
+        let mut on_target = match (self.get_piece(mov.from), mov.tipo) {
+            (_, MoveType::Promotion(pt)) => {
+                gains[0] += see_value(pt) - see_value(Pawn);
+                see_value(pt)
+            }
+            (Some(piece), _) => see_value(piece.tipo),
+            (None, _) => return 0,
+        };
+        let _ = board.set_square(mov.from, None);
+
+        let mut color = !self.turn;
+        while let Some((pos, pt)) = board.least_valuable_attacker(target, color) {
+            let previous = gains[gains.len() - 1];
+            gains.push(on_target - previous);
+            on_target = see_value(pt);
+            let _ = board.set_square(pos, None);
+            color = !color;
+        }
+        // Going backwards, every side picks between capturing and standing pat
+        while gains.len() > 1 {
+            let last = gains.pop().unwrap();
+            let previous = gains.last_mut().unwrap();
+            *previous = -(-*previous).max(last);
+        }
+        gains[0]
+    }
+    // The kings get checked by the rules of the variant
+    pub fn validate(&self) -> Vec<SetupError> {
+        let mut problems = self.rules().validate(self);
+        for pos in Position::all() {
+            if let Some(piece!(_, Pawn)) = self.get_piece(pos) {
+                if pos.y == Position::ch2y('1') || pos.y == Position::ch2y('8') {
+                    problems.push(SetupError::PawnOnBackRank(pos));
+                }
+            }
+        }
+        let castling = self.castling;
+        let files_in_order = castling.long_rook_file < castling.king_file
+            && castling.king_file < castling.short_rook_file;
+        for &color in [White, Black].iter() {
+            let king_home = self
+                .get_square(self.king_home(color))
+                .contains(Piece::new(color, King));
+            let rook_at = |file| {
+                self.get_square(Position::new(file, Game::back_rank(color)))
+                    .contains(Piece::new(color, Rook))
+            };
+            if (castling.short(color) && !(king_home && rook_at(castling.short_rook_file)))
+                || (castling.long(color) && !(king_home && rook_at(castling.long_rook_file)))
+                || ((castling.short(color) || castling.long(color)) && !files_in_order)
+            {
+                problems.push(SetupError::BadCastlingRights(color));
+            }
+        }
+        if let Some(ep) = self.en_passant {
+            let (rank, pawn_dir, origin_dir) = match self.turn {
+                White => ('6', Down, Up),
+                Black => ('3', Up, Down),
+            };
+            let valid = ep.y == Position::ch2y(rank)
+                && self.get_square(ep).has_none()
+                && self
+                    .get_square(ep.go(pawn_dir))
+                    .contains(Piece::new(!self.turn, Pawn))
+                && self.get_square(ep.go(origin_dir)).has_none();
+            if !valid {
+                problems.push(SetupError::BadEnPassant(ep));
+            }
+        }
+        problems
+    }
+    // Grants every castling right that the placement of kings and rooks allows
+    pub fn reset_castling_rights(&mut self) {
+        self.castling = CastlingRights {
+            white_short: true,
+            white_long: true,
+            black_short: true,
+            black_long: true,
+            ..self.castling
+        };
+        for &color in [White, Black].iter() {
+            if !self
+                .get_square(self.king_home(color))
+                .contains(Piece::new(color, King))
+            {
+                self.castling.remove(color);
+            }
+            let rank = Game::back_rank(color);
+            for &file in [self.castling.long_rook_file, self.castling.short_rook_file].iter() {
+                let pos = Position::new(file, rank);
+                if !self.get_square(pos).contains(Piece::new(color, Rook)) {
+                    self.castling.remove_rook(pos);
+                }
+            }
+        }
+    }
+    // Whether the side to move has its king attacked
+    pub fn is_check(&self) -> bool {
+        let king = Piece::new(self.turn, King);
+        match Position::all().find(|&pos| self.get_square(pos).contains(king)) {
+            Some(pos) => self.can_be_eaten_by(pos, !self.turn),
+            None => false,
+        }
     }
     pub fn can_eat_king(&self) -> bool {
         let color = self.turn;
@@ -656,28 +941,13 @@ impl Game {
                 return self.can_be_eaten_by(pos, color);
             }
         }
-        unreachable!("There is no king on the board")
+        false
     }
 }
 
 impl fmt::Display for Game {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} Plays\n", self.turn)?;
-        write!(f, "    a b c d e f g h  \n")?;
-        write!(f, "  +-----------------+\n")?;
-        for r in (2..10).rev() {
-            write!(f, "{} |", r - 1)?;
-            for c in 2..10 {
-                match self.board[r][c] {
-                    Some(ref p) => write!(f, " {}", p)?,
-                    None => write!(f, "")?,
-                };
-            }
-            write!(f, " | {}\n", r - 1)?;
-        }
-        write!(f, "  +-----------------+\n")?;
-        write!(f, "    a b c d e f g h  \n")?;
-        Ok(())
+        write!(f, "{}", self.render(&RenderOptions::default()))
     }
 }
 
@@ -923,6 +1193,367 @@ fn test_game_1() {
     game.show();
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MoveError {
+    NotASquare,
+    EmptySquare,
+    WrongColor,
+    BadMovement(PieceType),
+    MustPromote,
+    BadLongCastling,
+    BadShortCastling,
+    BadDrop,
+    KingInCheck,
+    MustCapture,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MoveError::NotASquare => write!(f, "Not even a valid square"),
+            MoveError::EmptySquare => write!(f, "Empty from square"),
+            MoveError::WrongColor => write!(f, "Wrong color"),
+            MoveError::BadMovement(pt) => write!(f, "Bad {:?} movement", pt),
+            MoveError::MustPromote => write!(f, "You must promote that pawn"),
+            MoveError::BadLongCastling => write!(f, "Bad long castling"),
+            MoveError::BadShortCastling => write!(f, "Bad short castling"),
+            MoveError::BadDrop => write!(f, "Bad drop"),
+            MoveError::KingInCheck => write!(f, "The king can be eaten after that move"),
+            MoveError::MustCapture => write!(f, "You must capture when you can"),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParseError {
+    UnknownFile(char),
+    UnknownRank(char),
+    UnknownPiece(char),
+    MalformedMove,
+    IllegalMove,
+    AmbiguousMove,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnknownFile(c) => write!(f, "Bad letter '{}'", c),
+            ParseError::UnknownRank(c) => write!(f, "Bad number '{}'", c),
+            ParseError::UnknownPiece(c) => write!(f, "'{}' is not a valid piece type", c),
+            ParseError::MalformedMove => write!(f, "Not a valid move string"),
+            ParseError::IllegalMove => write!(f, "No legal move matches"),
+            ParseError::AmbiguousMove => write!(f, "More than one legal move matches"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SetupError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank(Position),
+    OpponentInCheck(Color),
+    BadCastlingRights(Color),
+    BadEnPassant(Position),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SetupError::MissingKing(color) => write!(f, "{} has no king", color),
+            SetupError::TooManyKings(color) => write!(f, "{} has more than one king", color),
+            SetupError::PawnOnBackRank(pos) => write!(f, "Pawn on the back rank at {}", pos),
+            SetupError::OpponentInCheck(color) => {
+                write!(f, "{} is in check but it's not their turn", color)
+            }
+            SetupError::BadCastlingRights(color) => {
+                write!(f, "{} can castle without its king and rook in place", color)
+            }
+            SetupError::BadEnPassant(pos) => write!(f, "En passant on {} isn't possible", pos),
+        }
+    }
+}
+
+impl Error for SetupError {}
+
+// The files where the king and the castling rooks start are the same for both colours, they
+// only differ from e, h and a in Chess960
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CastlingRights {
+    pub white_short: bool,
+    pub white_long: bool,
+    pub black_short: bool,
+    pub black_long: bool,
+    pub king_file: X,
+    pub short_rook_file: X,
+    pub long_rook_file: X,
+}
+
+impl CastlingRights {
+    pub fn all() -> CastlingRights {
+        CastlingRights {
+            white_short: true,
+            white_long: true,
+            black_short: true,
+            black_long: true,
+            king_file: Position::ch2x('e'),
+            short_rook_file: Position::ch2x('h'),
+            long_rook_file: Position::ch2x('a'),
+        }
+    }
+    pub fn none() -> CastlingRights {
+        CastlingRights {
+            white_short: false,
+            white_long: false,
+            black_short: false,
+            black_long: false,
+            ..CastlingRights::all()
+        }
+    }
+    pub fn any(&self) -> bool {
+        self.white_short || self.white_long || self.black_short || self.black_long
+    }
+    pub fn is_classical(&self) -> bool {
+        self.king_file == Position::ch2x('e')
+            && self.short_rook_file == Position::ch2x('h')
+            && self.long_rook_file == Position::ch2x('a')
+    }
+    pub fn rook_file(&self, short: bool) -> X {
+        if short {
+            self.short_rook_file
+        } else {
+            self.long_rook_file
+        }
+    }
+    pub fn set(&mut self, color: Color, short: bool) {
+        match (color, short) {
+            (White, true) => self.white_short = true,
+            (White, false) => self.white_long = true,
+            (Black, true) => self.black_short = true,
+            (Black, false) => self.black_long = true,
+        }
+    }
+    pub fn short(&self, color: Color) -> bool {
+        match color {
+            White => self.white_short,
+            Black => self.black_short,
+        }
+    }
+    pub fn long(&self, color: Color) -> bool {
+        match color {
+            White => self.white_long,
+            Black => self.black_long,
+        }
+    }
+    pub fn remove(&mut self, color: Color) {
+        match color {
+            White => {
+                self.white_short = false;
+                self.white_long = false;
+            }
+            Black => {
+                self.black_short = false;
+                self.black_long = false;
+            }
+        }
+    }
+    // Drops the right that depends on the rook starting at pos, if there is one
+    pub fn remove_rook(&mut self, pos: Position) {
+        let rank = |color| Game::back_rank(color);
+        if pos == Position::new(self.long_rook_file, rank(White)) {
+            self.white_long = false;
+        } else if pos == Position::new(self.short_rook_file, rank(White)) {
+            self.white_short = false;
+        } else if pos == Position::new(self.long_rook_file, rank(Black)) {
+            self.black_long = false;
+        } else if pos == Position::new(self.short_rook_file, rank(Black)) {
+            self.black_short = false;
+        }
+    }
+}
+
+#[test]
+fn test_move_errors() {
+    let mut game = Game::new();
+    assert_eq!(
+        game.make_move(&Move::safe_from_string("e7e5")),
+        Err(MoveError::WrongColor)
+    );
+    assert_eq!(
+        game.make_move(&Move::safe_from_string("e3e4")),
+        Err(MoveError::EmptySquare)
+    );
+    assert_eq!(
+        game.make_move(&Move::safe_from_string("b1b3")),
+        Err(MoveError::BadMovement(Knight))
+    );
+    assert_eq!(
+        game.make_move(&Move::safe_from_string("O-O")),
+        Err(MoveError::BadShortCastling)
+    );
+    assert!(game.make_move(&Move::safe_from_string("e2e4")).is_ok());
+    assert!(game.make_move(&Move::safe_from_string("f7f6")).is_ok());
+    assert!(game.make_move(&Move::safe_from_string("d1h5")).is_ok());
+    assert!(game.make_move(&Move::safe_from_string("g7g6")).is_ok());
+    assert!(game.make_move(&Move::safe_from_string("h5g6")).is_ok());
+    assert_eq!(
+        game.make_move(&Move::safe_from_string("a7a6")),
+        Err(MoveError::KingInCheck)
+    );
+}
+
+#[test]
+fn test_validate() {
+    let mut game = Game::new();
+    assert!(game.validate().is_empty());
+    game.clear();
+    assert_eq!(
+        game.validate(),
+        vec![
+            SetupError::MissingKing(White),
+            SetupError::MissingKing(Black),
+            SetupError::BadCastlingRights(White),
+            SetupError::BadCastlingRights(Black),
+        ]
+    );
+    let e1 = Position::safe_from_chars('e', '1');
+    let e8 = Position::safe_from_chars('e', '8');
+    let a8 = Position::safe_from_chars('a', '8');
+    game.set_square(e1, Some(Piece::new(White, King))).unwrap();
+    game.set_square(e8, Some(Piece::new(Black, King))).unwrap();
+    game.reset_castling_rights();
+    assert_eq!(game.castling, CastlingRights::none());
+    assert!(game.validate().is_empty());
+    game.set_square(a8, Some(Piece::new(White, Pawn))).unwrap();
+    assert_eq!(game.validate(), vec![SetupError::PawnOnBackRank(a8)]);
+    game.set_square(a8, Some(Piece::new(White, Rook))).unwrap();
+    assert_eq!(game.validate(), vec![SetupError::OpponentInCheck(Black)]);
+    game.turn = Black;
+    assert!(game.validate().is_empty());
+    game.set_square(
+        Position::safe_from_chars('h', '1'),
+        Some(Piece::new(Black, King)),
+    )
+    .unwrap();
+    assert_eq!(game.validate(), vec![SetupError::TooManyKings(Black)]);
+    game.en_passant = Some(Position::safe_from_chars('d', '3'));
+    assert_eq!(
+        game.validate(),
+        vec![
+            SetupError::TooManyKings(Black),
+            SetupError::BadEnPassant(Position::safe_from_chars('d', '3')),
+        ]
+    );
+}
+
+#[test]
+fn test_castling_rights() {
+    let mut game = Game::new();
+    for mov in [
+        "e2e4", "e7e5", "g1f3", "g8f6", "f1c4", "f8c5", "h1g1", "h8g8", "g1h1",
+    ]
+    .iter()
+    {
+        assert!(game.make_move(&Move::safe_from_string(mov)).is_ok());
+    }
+    assert!(!game.castling.short(White));
+    assert!(!game.castling.short(Black));
+    assert!(game.castling.long(White));
+    assert_eq!(
+        game.make_move(&Move::safe_from_string("O-O")),
+        Err(MoveError::BadShortCastling)
+    );
+}
+
+#[test]
+fn test_en_passant() {
+    let mut game = Game::new();
+    for mov in ["e2e4", "a7a6", "e4e5", "d7d5"].iter() {
+        assert!(game.make_move(&Move::safe_from_string(mov)).is_ok());
+    }
+    let d6 = Position::safe_from_chars('d', '6');
+    assert_eq!(game.en_passant, Some(d6));
+    assert!(game
+        .get_all_valid_moves()
+        .iter()
+        .any(|mov| mov.mov == Move::safe_from_string("e5d6")));
+    assert!(game.make_move(&Move::safe_from_string("e5d6")).is_ok());
+    assert_eq!(game.get_piece(Position::safe_from_chars('d', '5')), None);
+    assert_eq!(game.get_piece(d6), Some(Piece::new(White, Pawn)));
+    assert_eq!(game.en_passant, None);
+    assert_eq!(game.halfmove_clock, 0);
+    assert_eq!(game.fullmove_number, 3);
+}
+
+#[test]
+fn test_coordinate_notation() {
+    let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+    let castle = game.parse_move("e8g8").unwrap();
+    assert_eq!(castle.tipo, MoveType::ShortCastling);
+    assert_eq!(game.format_move(&castle), "e8g8");
+    assert_eq!(
+        game.parse_move("e8c8").unwrap().tipo,
+        MoveType::LongCastling
+    );
+    assert_eq!(game.format_move(&Move::safe_from_string("O-O-O")), "e8c8");
+    assert_eq!(game.parse_move("e8f8").unwrap().tipo, MoveType::Normal);
+    assert_eq!(game.parse_move("a1a2").unwrap().tipo, MoveType::Normal);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(Move::from_string("z3a4"), Err(ParseError::UnknownFile('z')));
+    assert_eq!(Move::from_string("e9a2"), Err(ParseError::UnknownRank('9')));
+    assert_eq!(
+        Move::from_string("e7e8x"),
+        Err(ParseError::UnknownPiece('x'))
+    );
+    assert_eq!(Move::from_string("aaa"), Err(ParseError::MalformedMove));
+}
+
+#[test]
+fn test_see() {
+    let see = |fen: &str, mov: &str| {
+        let game = Game::from_fen(fen).unwrap();
+        game.see(&game.parse_move(mov).unwrap(), &EvalParams::default())
+    };
+    // The rook takes a pawn nobody defends
+    assert_eq!(
+        see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+        10
+    );
+    // The knight takes a pawn defended by a knight, with the rook and queen behind it
+    assert_eq!(
+        see(
+            "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+            "d3e5"
+        ),
+        -20
+    );
+    // Pawn takes knight, the recapture doesn't change that
+    assert_eq!(see("4k3/8/3p4/4n3/3P4/8/8/4K3 w - - 0 1", "d4e5"), 20);
+    // The queen takes a defended pawn and gets taken back
+    assert_eq!(see("4k3/5p2/4p3/8/8/8/4Q3/4K3 w - - 0 1", "e2e6"), -80);
+    // The king can't take a defended piece
+    assert_eq!(see("4k3/8/8/8/8/6b1/5n2/4K3 w - - 0 1", "e1f2"), -970);
+    assert_eq!(see("4k3/8/8/8/8/8/5n2/4K3 w - - 0 1", "e1f2"), 30);
+    // En passant and quiet moves
+    assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 10);
+    assert_eq!(see("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", "e2e4"), 0);
+
+    // Tuned values change the exchange too
+    let game = Game::from_fen("4k3/8/3p4/4n3/3P4/8/8/4K3 w - - 0 1").unwrap();
+    let params = EvalParams {
+        knight: 45,
+        ..EvalParams::default()
+    };
+    assert_eq!(game.see(&game.parse_move("d4e5").unwrap(), &params), 35);
+}
+
 use self::Color::{Black, White};
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub enum Color {
@@ -937,6 +1568,12 @@ impl Color {
             Black => -1,
         }
     }
+    pub fn index(&self) -> usize {
+        match *self {
+            White => 0,
+            Black => 1,
+        }
+    }
 }
 
 impl fmt::Display for Color {
@@ -980,7 +1617,7 @@ pub enum PieceType {
 }
 
 impl PieceType {
-    pub fn from_char(c: char) -> Result<PieceType, &'static str> {
+    pub fn from_char(c: char) -> Result<PieceType, ParseError> {
         match c {
             'r' => Ok(Rook),
             'n' => Ok(Knight),
@@ -988,7 +1625,7 @@ impl PieceType {
             'q' => Ok(Queen),
             'k' => Ok(King),
             'p' => Ok(Pawn),
-            _ => Err("Not a valid piece type"),
+            _ => Err(ParseError::UnknownPiece(c)),
         }
     }
     pub fn safe_from_char(c: char) -> PieceType {
@@ -1075,19 +1712,19 @@ impl fmt::Display for Piece {
             f,
             "{}",
             match *self {
-                piece!(Black, Rook) => 'R',
-                piece!(Black, Knight) => 'N',
-                piece!(Black, Bishop) => 'B',
-                piece!(Black, Queen) => 'Q',
-                piece!(Black, King) => 'K',
-                piece!(Black, Pawn) => 'P',
-
-                piece!(White, Rook) => 'r',
-                piece!(White, Knight) => 'n',
-                piece!(White, Bishop) => 'b',
-                piece!(White, Queen) => 'q',
-                piece!(White, King) => 'k',
-                piece!(White, Pawn) => 'p',
+                piece!(White, Rook) => 'R',
+                piece!(White, Knight) => 'N',
+                piece!(White, Bishop) => 'B',
+                piece!(White, Queen) => 'Q',
+                piece!(White, King) => 'K',
+                piece!(White, Pawn) => 'P',
+
+                piece!(Black, Rook) => 'r',
+                piece!(Black, Knight) => 'n',
+                piece!(Black, Bishop) => 'b',
+                piece!(Black, Queen) => 'q',
+                piece!(Black, King) => 'k',
+                piece!(Black, Pawn) => 'p',
             }
         )
     }
@@ -1307,6 +1944,8 @@ pub enum MoveType {
     LongCastling,
     ShortCastling,
     Promotion(PieceType),
+    // Crazyhouse only, the piece comes from the pocket and from is the same as to
+    Drop(PieceType),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -1330,7 +1969,7 @@ impl Move {
             _ => false,
         }
     }
-    pub fn from_string(s: &str) -> Result<Move, &'static str> {
+    pub fn from_string(s: &str) -> Result<Move, ParseError> {
         if s == "O-O" {
             Ok(Move {
                 from: Position::new(0, 0),
@@ -1343,29 +1982,39 @@ impl Move {
                 to: Position::new(0, 0),
                 tipo: MoveType::LongCastling,
             })
+        } else if s.len() == 4 && s.chars().nth(1) == Some('@') {
+            let piece: char = s.chars().next().ok_or(ParseError::MalformedMove)?;
+            let to_x: char = s.chars().nth(2).ok_or(ParseError::MalformedMove)?;
+            let to_y: char = s.chars().nth(3).ok_or(ParseError::MalformedMove)?;
+            let to = Position::from_chars(to_x, to_y)?;
+            Ok(Move {
+                from: to,
+                to,
+                tipo: MoveType::Drop(PieceType::from_char(piece.to_ascii_lowercase())?),
+            })
         } else if s.len() == 4 {
-            let from_x: char = s.chars().nth(0).ok_or("Not found")?;
-            let from_y: char = s.chars().nth(1).ok_or("Not found")?;
-            let to_x: char = s.chars().nth(2).ok_or("Not found")?;
-            let to_y: char = s.chars().nth(3).ok_or("Not found")?;
+            let from_x: char = s.chars().nth(0).ok_or(ParseError::MalformedMove)?;
+            let from_y: char = s.chars().nth(1).ok_or(ParseError::MalformedMove)?;
+            let to_x: char = s.chars().nth(2).ok_or(ParseError::MalformedMove)?;
+            let to_y: char = s.chars().nth(3).ok_or(ParseError::MalformedMove)?;
             Ok(Move {
                 from: Position::from_chars(from_x, from_y)?,
                 to: Position::from_chars(to_x, to_y)?,
                 tipo: MoveType::Normal,
             })
         } else if s.len() == 5 {
-            let from_x: char = s.chars().nth(0).ok_or("Nojt found")?;
-            let from_y: char = s.chars().nth(1).ok_or("Not found")?;
-            let to_x: char = s.chars().nth(2).ok_or("Not found")?;
-            let to_y: char = s.chars().nth(3).ok_or("Not found")?;
-            let prom: char = s.chars().nth(4).ok_or("Not found")?;
+            let from_x: char = s.chars().nth(0).ok_or(ParseError::MalformedMove)?;
+            let from_y: char = s.chars().nth(1).ok_or(ParseError::MalformedMove)?;
+            let to_x: char = s.chars().nth(2).ok_or(ParseError::MalformedMove)?;
+            let to_y: char = s.chars().nth(3).ok_or(ParseError::MalformedMove)?;
+            let prom: char = s.chars().nth(4).ok_or(ParseError::MalformedMove)?;
             Ok(Move {
                 from: Position::from_chars(from_x, from_y)?,
                 to: Position::from_chars(to_x, to_y)?,
                 tipo: MoveType::Promotion(PieceType::from_char(prom)?),
             })
         } else {
-            Err("Not a valid string")
+            Err(ParseError::MalformedMove)
         }
     }
     pub fn safe_from_string(s: &str) -> Move {
@@ -1380,6 +2029,9 @@ impl fmt::Display for Move {
             MoveType::LongCastling => write!(f, "O-O-O"),
             MoveType::ShortCastling => write!(f, "O-O"),
             MoveType::Promotion(ref pt) => write!(f, "{}{}{}", self.from, self.to, pt),
+            MoveType::Drop(ref pt) => {
+                write!(f, "{}@{}", pt.to_string().to_uppercase(), self.to)
+            }
         }
     }
 }
@@ -1403,12 +2055,13 @@ pub struct ValuedMove {
     pub value: BoardValue,
 }
 
-use self::BoardValue::Invalid;
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+use self::BoardValue::{Invalid, Value, WonBlack, WonWhite};
+// Wins carry the number of plies until mate, so that a faster win is better for the winner
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BoardValue {
-    WonBlack,
+    WonBlack(u32),
     Value(i32),
-    WonWhite,
+    WonWhite(u32),
     Invalid,
 }
 
@@ -1419,13 +2072,35 @@ impl BoardValue {
             _ => true,
         }
     }
+    fn sort_key(&self) -> (u8, i64) {
+        match *self {
+            WonBlack(plies) => (0, i64::from(plies)),
+            Value(v) => (1, i64::from(v)),
+            WonWhite(plies) => (2, -i64::from(plies)),
+            Invalid => (3, 0),
+        }
+    }
+}
+
+impl Ord for BoardValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+impl PartialOrd for BoardValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 #[test]
 fn test_cmp() {
-    assert!(BoardValue::WonWhite > BoardValue::Value(200));
-    assert!(BoardValue::WonWhite > BoardValue::WonBlack);
-    assert!(BoardValue::WonBlack < BoardValue::Value(-100));
+    assert!(BoardValue::WonWhite(5) > BoardValue::Value(200));
+    assert!(BoardValue::WonWhite(5) > BoardValue::WonBlack(1));
+    assert!(BoardValue::WonBlack(5) < BoardValue::Value(-100));
+    assert!(BoardValue::WonWhite(1) > BoardValue::WonWhite(3));
+    assert!(BoardValue::WonBlack(1) < BoardValue::WonBlack(3));
+    assert!(BoardValue::Invalid > BoardValue::WonWhite(1));
 }
 
 impl ValuedMove {
@@ -1495,11 +2170,11 @@ fn test_valuedmove_partial_ord() {
     let mut first = ValuedMove::invalid();
     let mut second = ValuedMove::invalid();
     assert_eq!(first, second);
-    first.value = BoardValue::WonWhite;
+    first.value = BoardValue::WonWhite(3);
     second.value = BoardValue::Value(10);
     assert!(first > second);
     first.value = BoardValue::Value(1);
-    second.value = BoardValue::WonBlack;
+    second.value = BoardValue::WonBlack(3);
     assert!(first > second);
 }
 
@@ -1543,15 +2218,15 @@ impl Position {
     pub fn new(x: X, y: Y) -> Position {
         Position { x: x, y: y }
     }
-    pub fn from_chars(x: char, y: char) -> Result<Position, &'static str> {
+    pub fn from_chars(x: char, y: char) -> Result<Position, ParseError> {
         Ok(Position {
             x: match x {
                 c @ 'a'..='h' => Position::ch2x(c),
-                _ => return Err("Bad letter"),
+                _ => return Err(ParseError::UnknownFile(x)),
             },
             y: match y {
                 c @ '1'..='8' => Position::ch2y(c),
-                _ => return Err("Bad Number"),
+                _ => return Err(ParseError::UnknownRank(y)),
             },
         })
     }
@@ -1608,6 +2283,13 @@ impl Position {
     pub fn left(&self) -> Position {
         Position::new(self.x - 1, self.y)
     }
+    // Index from 0 (a1) to 63 (h8), only meaningful for squares on the board
+    pub fn index(&self) -> usize {
+        (self.y as usize - 2) * 8 + (self.x as usize - 2)
+    }
+    pub fn from_index(index: usize) -> Position {
+        Position::new((index % 8) as X + 2, (index / 8) as Y + 2)
+    }
     pub fn all() -> AllPositionsIterator {
         AllPositionsIterator {
             curr: Position::new(0, 0),
@@ -1836,6 +2518,17 @@ fn test_positions() {
     );
 }
 
+#[test]
+fn position_index() {
+    assert_eq!(Position::safe_from_chars('a', '1').index(), 0);
+    assert_eq!(Position::safe_from_chars('h', '1').index(), 7);
+    assert_eq!(Position::safe_from_chars('h', '8').index(), 63);
+    for (i, pos) in Position::all().enumerate() {
+        assert_eq!(pos.index(), i);
+        assert_eq!(Position::from_index(i), pos);
+    }
+}
+
 #[test]
 fn position_from_string() {
     assert_eq!(Position { x: 2, y: 2 }, Position::safe_from_chars('a', '1'));
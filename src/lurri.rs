@@ -1,81 +1,856 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use game::*;
 // use game::PieceType::{King, Queen, Rook, Bishop, Knight, Pawn};
-use game::BoardValue::{Invalid, Value, WonBlack, WonWhite};
-use game::Color::{Black, White};
+use book::Book;
+use eval::EvalParams;
+use game::BoardValue::{Value, WonBlack, WonWhite};
+use tablebase::{Tablebase, Wdl};
+use time::{Clock, TimeManager};
+use tt::{Bound, TranspositionTable, TtEntry};
+use variant::{Outcome, Variant};
+
+// A mate found at ply n from the root scores WON - n for the winner
+const WON: i32 = 1_000_000;
+const INFINITY: i32 = WON + 1;
+const MAX_PLY: i32 = 1_000;
+const WON_CENTIPAWNS: i32 = 100_000;
+// Tablebase wins are worth less than any mate but more than any evaluation
+const TB_WIN: i32 = 5_000;
+// Null moves are searched this much shallower, plus one more ply on deep searches
+const NULL_MOVE_REDUCTION: u32 = 2;
+// Moves searched at full depth before late move reductions start
+const FULL_DEPTH_MOVES: u32 = 3;
+// Half the width of the first aspiration window, it doubles on every fail
+const ASPIRATION_WINDOW: i32 = 20;
+// Placeholder in the current line for a null move, it never matches a real move
+const NULL_MOVE: Move = Move {
+    from: Position { x: 0, y: 0 },
+    to: Position { x: 0, y: 0 },
+    tipo: MoveType::Normal,
+};
 
-pub fn get_move(game: &Game, max_ply: u32) -> Move {
-    get_best_move(game, 0, max_ply).mov
+// Pruning techniques that can be switched off to compare their effect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchFeatures {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub aspiration_windows: bool,
+    pub principal_variation_search: bool,
+    pub extensions: bool,
+    pub quiescence: bool,
 }
 
-pub fn get_best_move(game: &Game, ply: u32, max_ply: u32) -> ValuedMove {
-    let mut moves: Vec<ValuedMove> = game.get_all_valid_moves();
-    for mov in moves.iter_mut() {
-        let mut aux_game: Game = game.clone();
-        if ply == max_ply {
-            mov.value = aux_game.evaluate_move(&mov.mov)
-        } else {
-            if let Err(..) = aux_game.make_move(&mov.mov) {
-                mov.value = Invalid;
-                continue;
-            };
-            mov.value = get_best_move(&aux_game, ply + 1, max_ply).value
+impl SearchFeatures {
+    pub fn all() -> SearchFeatures {
+        SearchFeatures {
+            null_move: true,
+            late_move_reductions: true,
+            aspiration_windows: true,
+            principal_variation_search: true,
+            extensions: true,
+            quiescence: true,
+        }
+    }
+    pub fn none() -> SearchFeatures {
+        SearchFeatures {
+            null_move: false,
+            late_move_reductions: false,
+            aspiration_windows: false,
+            principal_variation_search: false,
+            extensions: false,
+            quiescence: false,
         }
     }
+}
 
-    if !moves.iter().any(|mov| mov.value.is_valid()) {
-        return ValuedMove::from_value(match game.turn {
-            White => WonBlack,
-            Black => WonWhite,
-        });
-    };
-    let mov: ValuedMove = match game.turn {
-        White => *moves
-            .iter()
-            .filter(|mov| mov.value.is_valid())
-            .max()
-            .unwrap(),
-        Black => *moves
-            .iter()
-            .filter(|mov| mov.value.is_valid())
-            .min()
-            .unwrap(),
-    };
-    mov
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: BoardValue,
+    pub pv: Vec<Move>,
+    pub depth: u32,
+    pub nodes: u64,
+    pub tb_hits: u64,
+    pub elapsed: Duration,
 }
 
-impl Game {
-    pub fn evaluate(&self) -> i32 {
-        let mut moves = Vec::with_capacity(80);
-        Position::all().fold(0, |acc, pos| match self.get_piece(pos) {
-            None => acc,
-            Some(piece) => {
-                moves.clear();
-                acc + piece.get_value() * 10
-                    + piece.color.get_sign() * (self.get_valid_moves(pos, &mut moves).len() as i32)
+impl SearchResult {
+    pub fn nps(&self) -> u64 {
+        let micros = self.elapsed.as_micros() as u64;
+        (self.nodes * 1_000_000).checked_div(micros).unwrap_or(0)
+    }
+    // Moves until mate from the point of view of color, negative when color gets mated
+    pub fn mate_in(&self, color: Color) -> Option<i32> {
+        match self.score {
+            WonWhite(plies) => Some(color.get_sign() * (plies as i32 + 1) / 2),
+            WonBlack(plies) => Some(-color.get_sign() * (plies as i32 + 1) / 2),
+            _ => None,
+        }
+    }
+    // Score in centipawns from the point of view of color, mates in n moves are reported
+    // as 100000 + n and -100000 - n like xboard expects
+    pub fn centipawns(&self, color: Color) -> i32 {
+        match (self.mate_in(color), self.score) {
+            (Some(n), _) if n > 0 => WON_CENTIPAWNS + n,
+            (Some(n), _) => -WON_CENTIPAWNS + n,
+            (None, Value(v)) => color.get_sign() * v * 10,
+            (None, _) => 0,
+        }
+    }
+    // The principal variation in coordinate notation, starting from game
+    pub fn pv_string(&self, game: &Game) -> String {
+        let mut game = game.clone();
+        let mut moves = Vec::with_capacity(self.pv.len());
+        for mov in self.pv.iter() {
+            moves.push(game.format_move(mov));
+            if game.make_move(mov).is_err() {
+                break;
+            }
+        }
+        moves.join(" ")
+    }
+}
+
+// Positions used to compare search changes by their node count
+pub const BENCH_POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r2q1rk1/ppp2ppp/2np1n2/2b1p1B1/2B1P1b1/2NP1N2/PPP2PPP/R2Q1RK1 w - - 0 8",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+];
+
+pub const MAX_DEPTH: u32 = 64;
+// Searches without any other limit go this deep, in every protocol
+pub const DEFAULT_DEPTH: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchLimits {
+    pub depth: u32,
+    // The search stops once this much time has passed, but it always finishes depth 1
+    pub time: Option<Duration>,
+    // Playing on a clock the time manager decides when to stop
+    pub clock: Option<Clock>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth,
+            time: None,
+            clock: None,
+        }
+    }
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits {
+            depth: MAX_DEPTH,
+            time: Some(time),
+            clock: None,
+        }
+    }
+    pub fn clock(clock: Clock) -> SearchLimits {
+        SearchLimits {
+            depth: MAX_DEPTH,
+            time: None,
+            clock: Some(clock),
+        }
+    }
+}
+
+const DEFAULT_HASH_MB: usize = 16;
+const DEFAULT_BOOK_DEPTH: u32 = 20;
+pub const MAX_THREADS: usize = 64;
+
+// Lazy SMP: every thread runs its own iterative deepening over the same position and they
+// only cooperate through the shared transposition table. The result always comes from the
+// main thread, so with a single thread the search is deterministic.
+pub struct Engine {
+    threads: usize,
+    tt: TranspositionTable,
+    book: Option<Book>,
+    // Plies from the start of the game during which the book gets used
+    book_depth: u32,
+    tablebase: Option<Tablebase>,
+    features: SearchFeatures,
+    eval: EvalParams,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            threads: 1,
+            tt: TranspositionTable::new(DEFAULT_HASH_MB),
+            book: None,
+            book_depth: DEFAULT_BOOK_DEPTH,
+            tablebase: None,
+            features: SearchFeatures::all(),
+            eval: EvalParams::default(),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.clamp(1, MAX_THREADS);
+    }
+
+    pub fn set_hash(&mut self, megabytes: usize) {
+        self.tt = TranspositionTable::new(megabytes);
+    }
+
+    pub fn set_book(&mut self, book: Option<Book>) {
+        self.book = book;
+    }
+
+    pub fn set_book_depth(&mut self, plies: u32) {
+        self.book_depth = plies;
+    }
+
+    pub fn book_move(&self, game: &Game) -> Option<Move> {
+        // Polyglot books only hold standard chess, variants starting from the same setup would
+        // get its moves
+        if game.variant != Variant::Standard {
+            return None;
+        }
+        let ply = game.fullmove_number.saturating_sub(1) * 2 + (game.turn == Color::Black) as u32;
+        if ply >= self.book_depth {
+            return None;
+        }
+        self.book.as_ref()?.pick(game, &mut rand::thread_rng())
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Tablebase>) {
+        self.tablebase = tablebase;
+    }
+
+    pub fn features(&self) -> SearchFeatures {
+        self.features
+    }
+
+    pub fn set_features(&mut self, features: SearchFeatures) {
+        self.features = features;
+    }
+
+    pub fn eval_params(&self) -> EvalParams {
+        self.eval
+    }
+
+    pub fn set_eval_params(&mut self, eval: EvalParams) {
+        self.eval = eval;
+    }
+
+    // Forget everything learned in previous searches, for a new game
+    pub fn clear(&self) {
+        self.tt.clear();
+    }
+
+    // Searches every bench position from an empty table, returning the total node count and
+    // time spent
+    pub fn bench(&self, depth: u32) -> (u64, Duration) {
+        let mut nodes = 0;
+        let mut elapsed = Duration::new(0, 0);
+        for fen in BENCH_POSITIONS.iter() {
+            self.clear();
+            let result = self.search(&Game::from_fen(fen).unwrap(), depth, |_| {});
+            nodes += result.nodes;
+            elapsed += result.elapsed;
+        }
+        (nodes, elapsed)
+    }
+
+    pub fn get_move(&self, game: &Game, max_ply: u32) -> SearchResult {
+        self.search(game, max_ply, |_| {})
+    }
+
+    pub fn search<F>(&self, game: &Game, max_depth: u32, report: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        self.search_limited(game, SearchLimits::depth(max_depth), report)
+    }
+
+    // Iterative deepening until the limits are hit, report gets called after every finished
+    // iteration. Positions found in the book or in the tablebases are answered right away with
+    // a depth 0 result, and so are games already won by the goal of the variant
+    pub fn search_limited<F>(&self, game: &Game, limits: SearchLimits, report: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        self.search_interruptible(game, limits, &AtomicBool::new(false), report)
+    }
+
+    // Like search_limited, but setting interrupt from another thread ends the search as if it
+    // ran out of time
+    pub fn search_interruptible<F>(
+        &self,
+        game: &Game,
+        limits: SearchLimits,
+        interrupt: &AtomicBool,
+        mut report: F,
+    ) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        let start = Instant::now();
+        if let Some(outcome) = game.rules().goal(game) {
+            return SearchResult {
+                best_move: None,
+                score: outcome.value(),
+                pv: Vec::new(),
+                depth: 0,
+                nodes: 0,
+                tb_hits: 0,
+                elapsed: start.elapsed(),
+            };
+        }
+        if let Some(mov) = self.book_move(game) {
+            return SearchResult {
+                best_move: Some(mov),
+                score: Value(0),
+                pv: vec![mov],
+                depth: 0,
+                nodes: 0,
+                tb_hits: 0,
+                elapsed: start.elapsed(),
+            };
+        }
+        let tablebase_move = self.tablebase.as_ref().and_then(|tb| tb.best_move(game));
+        if let Some((mov, wdl)) = tablebase_move {
+            return SearchResult {
+                best_move: Some(mov),
+                score: board_value(tb_score(wdl, 0) * game.turn.get_sign()),
+                pv: vec![mov],
+                depth: 0,
+                nodes: 0,
+                tb_hits: 1,
+                elapsed: start.elapsed(),
+            };
+        }
+        let mut manager = limits.clock.map(|clock| TimeManager::new(&clock, game));
+        let time = match (limits.time, manager.as_ref().map(|m| m.hard_limit())) {
+            (Some(time), Some(hard)) => Some(time.min(hard)),
+            (time, hard) => time.or(hard),
+        };
+        let counters = Counters {
+            stop: AtomicBool::new(false),
+            nodes: AtomicU64::new(0),
+            tb_hits: AtomicU64::new(0),
+            deadline: time.map(|time| start + time),
+            interrupt,
+        };
+        let max_depth = limits.depth.clamp(1, MAX_DEPTH);
+        thread::scope(|scope| {
+            for id in 1..self.threads {
+                let counters = &counters;
+                scope.spawn(move || {
+                    // Half of the helpers start one ply deeper so they don't all follow the
+                    // main thread in lockstep
+                    let mut searcher = Searcher::new(self, counters, false);
+                    let mut pv = Vec::new();
+                    for depth in (1 + id as u32 % 2)..=max_depth {
+                        if counters.stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        searcher.iterate(game, depth, &mut pv);
+                    }
+                });
+            }
+
+            let mut searcher = Searcher::new(self, &counters, true);
+            let mut pv: Vec<Move> = Vec::new();
+            let mut result = None;
+            for depth in 1..=max_depth {
+                if depth > 1 && counters.should_stop() {
+                    break;
+                }
+                let score = searcher.iterate(game, depth, &mut pv);
+                // An unfinished iteration is useless, the previous one stands
+                if counters.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let iteration = SearchResult {
+                    best_move: pv.first().cloned(),
+                    score: board_value(score * game.turn.get_sign()),
+                    pv: pv.clone(),
+                    depth,
+                    nodes: counters.nodes.load(Ordering::Relaxed),
+                    tb_hits: counters.tb_hits.load(Ordering::Relaxed),
+                    elapsed: start.elapsed(),
+                };
+                report(&iteration);
+                let keep_searching = manager.as_mut().is_none_or(|manager| {
+                    manager.keep_searching(iteration.best_move, score, iteration.elapsed)
+                });
+                result = Some(iteration);
+                if !keep_searching {
+                    break;
+                }
             }
+            counters.stop.store(true, Ordering::Relaxed);
+            result.unwrap()
         })
     }
-    pub fn evaluate_move(&self, mov: &Move) -> BoardValue {
-        let mut aux_game = (*self).clone();
-        if let Err(..) = aux_game.make_move(mov) {
-            // println!("ERROR (engine generated an invalid move) mov: {}, reason: {}", mov, e);
-            Invalid
+}
+
+fn board_value(score: i32) -> BoardValue {
+    if score >= WON - MAX_PLY {
+        WonWhite((WON - score) as u32)
+    } else if score <= -WON + MAX_PLY {
+        WonBlack((WON + score) as u32)
+    } else {
+        Value(score)
+    }
+}
+
+fn tb_score(wdl: Wdl, ply: i32) -> i32 {
+    match wdl {
+        Wdl::Win => TB_WIN - ply,
+        Wdl::Loss => -TB_WIN + ply,
+        // Cursed wins and blessed losses are draws under the 50 move rule
+        _ => 0,
+    }
+}
+
+// Shared by all the threads of a search
+struct Counters<'a> {
+    // Set by the main thread when it is done, helpers bail out as soon as they see it
+    stop: AtomicBool,
+    nodes: AtomicU64,
+    tb_hits: AtomicU64,
+    deadline: Option<Instant>,
+    // Set from outside the search to end it early
+    interrupt: &'a AtomicBool,
+}
+
+impl<'a> Counters<'a> {
+    fn should_stop(&self) -> bool {
+        self.interrupt.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+struct Searcher<'a> {
+    engine: &'a Engine,
+    counters: &'a Counters<'a>,
+    // Only the main thread decides when the search is over, helpers may already be an
+    // iteration ahead while it hasn't got a move yet
+    main: bool,
+    // The principal variation of the previous iteration, which gets searched first
+    previous_pv: Vec<Move>,
+    // Scores of the previous iterations, the last one is the most recent
+    previous_scores: Vec<i32>,
+    // Depth of the current iteration
+    root_depth: u32,
+    // The moves leading from the root to the current node
+    line: Vec<Move>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(engine: &'a Engine, counters: &'a Counters<'a>, main: bool) -> Searcher<'a> {
+        Searcher {
+            engine,
+            counters,
+            main,
+            previous_pv: Vec::new(),
+            previous_scores: Vec::new(),
+            root_depth: 0,
+            line: Vec::new(),
+        }
+    }
+
+    // One iteration of iterative deepening. The root gets searched with a narrow window around
+    // an earlier score, and the side that fails gets widened until the score falls inside.
+    // Mobility makes scores swing between odd and even depths, so the window is centered on
+    // the score from two iterations ago
+    fn iterate(&mut self, game: &Game, depth: u32, pv: &mut Vec<Move>) -> i32 {
+        self.previous_pv = pv.clone();
+        self.root_depth = depth;
+        let mut delta = ASPIRATION_WINDOW;
+        let same_parity = self
+            .previous_scores
+            .len()
+            .checked_sub(2)
+            .map(|i| self.previous_scores[i]);
+        let (mut alpha, mut beta) = match same_parity {
+            Some(score) if self.engine.features.aspiration_windows && score.abs() < TB_WIN => {
+                (score - delta, score + delta)
+            }
+            _ => (-INFINITY, INFINITY),
+        };
+        loop {
+            let score = self.negamax(game, depth, alpha, beta, pv);
+            if self.stopped() {
+                return score;
+            }
+            delta *= 2;
+            if score <= alpha && alpha > -INFINITY {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                self.previous_scores.push(score);
+                return score;
+            }
+        }
+    }
+
+    // Running out of time or getting interrupted stops every thread, except during the first
+    // iteration of the main thread so that there is always a move to play
+    fn stopped(&self) -> bool {
+        if self.main && self.root_depth > 1 && self.counters.should_stop() {
+            self.counters.stop.store(true, Ordering::Relaxed);
+        }
+        self.counters.stop.load(Ordering::Relaxed)
+    }
+
+    fn pv_move(&self) -> Option<Move> {
+        let ply = self.line.len();
+        if ply < self.previous_pv.len() && self.previous_pv[..ply] == self.line[..] {
+            Some(self.previous_pv[ply])
         } else {
-            Value(aux_game.evaluate())
+            None
         }
     }
-    // pub fn evaluate_moves<'a>(&self, moves: &'a mut Vec<ValuedMove>) -> &'a mut Vec<ValuedMove> {
-    //     for mov in moves.iter_mut() {
-    //         mov.value = self.evaluate_move(&mov.mov)
-    //     }
-    //     moves
-    // }
+
+    // Scores are relative to the side to move
+    fn negamax(
+        &mut self,
+        game: &Game,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        self.counters.nodes.fetch_add(1, Ordering::Relaxed);
+        pv.clear();
+        if self.stopped() {
+            return 0;
+        }
+        let ply = self.line.len() as i32;
+        if ply > 0 {
+            if let Some(outcome) = game.rules().goal(game) {
+                return outcome_score(outcome, game.turn, ply);
+            }
+        }
+        // Only probe right after captures and pawn moves, which is when the position changes
+        // enough to enter a new table
+        if ply > 0 && game.halfmove_clock == 0 {
+            if let Some(wdl) = self
+                .engine
+                .tablebase
+                .as_ref()
+                .and_then(|tb| tb.probe_wdl(game))
+            {
+                self.counters.tb_hits.fetch_add(1, Ordering::Relaxed);
+                return tb_score(wdl, ply);
+            }
+        }
+        if depth == 0 {
+            return if self.engine.features.quiescence {
+                self.quiescence(game, alpha, beta)
+            } else {
+                game.evaluate_with(&self.engine.eval) * game.turn.get_sign()
+            };
+        }
+
+        let key = game.hash();
+        let entry = self.engine.tt.get(key);
+        if let Some(entry) = entry {
+            // Only cut when the stored score falls outside the window, so that scores inside
+            // it always come with a full principal variation
+            let score = score_from_tt(entry.score, ply);
+            let outside = match entry.bound {
+                Bound::Exact => score <= alpha || score >= beta,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if ply > 0 && entry.depth >= depth && outside {
+                return score;
+            }
+        }
+
+        let in_check = game.is_check();
+        // A null move that still fails high means any real move would too. Positions with only
+        // pawns left are skipped because zugzwang is common there and passing is not allowed
+        if self.engine.features.null_move
+            && ply > 0
+            && depth > NULL_MOVE_REDUCTION
+            && !in_check
+            && beta < WON - MAX_PLY
+            && self.line.last() != Some(&NULL_MOVE)
+            && has_pieces(game, game.turn)
+            && !game.rules().forced_captures()
+        {
+            let reduction = NULL_MOVE_REDUCTION + (depth > 6) as u32;
+            let mut child = game.clone();
+            child.turn = !child.turn;
+            child.en_passant = None;
+            self.line.push(NULL_MOVE);
+            let score = -self.negamax(
+                &child,
+                depth.saturating_sub(1 + reduction),
+                -beta,
+                -beta + 1,
+                &mut Vec::new(),
+            );
+            self.line.pop();
+            if score >= beta && !self.stopped() {
+                return beta;
+            }
+        }
+
+        let mut moves = game.candidate_moves();
+        moves.sort_by_cached_key(|mov| -capture_order(game, mov, &self.engine.eval));
+        if let Some(mov) = entry.and_then(|entry| entry.mov) {
+            move_to_front(&mut moves, mov);
+        }
+        if let Some(mov) = self.pv_move() {
+            move_to_front(&mut moves, mov);
+        }
+
+        // Forced replies only get counted when evading a check, elsewhere they are too rare to
+        // be worth making every move twice
+        let single_reply = self.engine.features.extensions
+            && in_check
+            && moves
+                .iter()
+                .filter(|mov| game.clone().make_move(mov).is_ok())
+                .count()
+                == 1;
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut searched = 0;
+        let mut child_pv = Vec::new();
+        for &mov in moves.iter() {
+            let quiet = mov.tipo == MoveType::Normal && !game.is_capture(&mov);
+            let mut child = game.clone();
+            if child.make_move(&mov).is_err() {
+                continue;
+            }
+            let features = self.engine.features;
+            let gives_check =
+                (features.extensions || features.late_move_reductions) && child.is_check();
+            searched += 1;
+            // Checks and forced replies get searched one ply deeper, so forced lines don't end
+            // right before the mate. Extensions stop once the line is twice the root depth
+            let extend = self.engine.features.extensions
+                && (gives_check || single_reply)
+                && (ply as u32) < 2 * self.root_depth;
+            let depth = depth + extend as u32;
+            self.line.push(mov);
+            // Late quiet moves are unlikely to be any good, so they get a shallower null window
+            // search first and only the ones that beat alpha get searched again at full depth
+            let reduce = self.engine.features.late_move_reductions
+                && depth >= 3
+                && searched > FULL_DEPTH_MOVES
+                && quiet
+                && !in_check
+                && !gives_check
+                && !extend;
+            // With principal variation search only the first move gets a full window, the rest
+            // just have to prove they are not better and get searched again when they are
+            let null_window = self.engine.features.principal_variation_search && searched > 1;
+            let mut score = alpha + 1;
+            if reduce {
+                let reduction = 1 + (depth >= 6 && searched > 2 * FULL_DEPTH_MOVES) as u32;
+                score = -self.negamax(
+                    &child,
+                    depth - 1 - reduction,
+                    -alpha - 1,
+                    -alpha,
+                    &mut child_pv,
+                );
+            }
+            if score > alpha && null_window {
+                score = -self.negamax(&child, depth - 1, -alpha - 1, -alpha, &mut child_pv);
+            }
+            if score > alpha && (!null_window || score < beta) {
+                score = -self.negamax(&child, depth - 1, -beta, -alpha, &mut child_pv);
+            }
+            self.line.pop();
+            if score > best {
+                best = score;
+                best_move = Some(mov);
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mov);
+                pv.extend_from_slice(&child_pv);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        if searched == 0 {
+            best = outcome_score(game.rules().no_moves(game), game.turn, ply);
+        }
+        if self.stopped() {
+            return 0;
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.engine.tt.store(
+            key,
+            TtEntry {
+                mov: best_move,
+                score: score_to_tt(best, ply),
+                depth,
+                bound,
+            },
+        );
+        best
+    }
+
+    // Keeps searching captures past the horizon until the position is quiet, so the evaluation
+    // never happens in the middle of an exchange. Captures that lose material according to
+    // the static exchange evaluation are skipped
+    fn quiescence(&mut self, game: &Game, mut alpha: i32, beta: i32) -> i32 {
+        if self.stopped() {
+            return 0;
+        }
+        if let Some(outcome) = game.rules().goal(game) {
+            return outcome_score(outcome, game.turn, self.line.len() as i32);
+        }
+        // When captures are forced the side to move can't stand pat while it has one
+        let forced = game.rules().forced_captures();
+        let mut best = -INFINITY;
+        if !forced {
+            let stand_pat = game.evaluate_with(&self.engine.eval) * game.turn.get_sign();
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best = stand_pat;
+        }
+
+        let mut captures: Vec<(i32, Move)> = game
+            .get_all_valid_moves()
+            .iter()
+            .filter(|m| game.is_capture(&m.mov))
+            .map(|m| (game.see(&m.mov, &self.engine.eval), m.mov))
+            .filter(|&(see, _)| forced || see >= 0)
+            .collect();
+        captures.sort_by_key(|&(see, _)| -see);
+        if forced && captures.is_empty() {
+            return game.evaluate_with(&self.engine.eval) * game.turn.get_sign();
+        }
+
+        for &(_, mov) in captures.iter() {
+            let mut child = game.clone();
+            if child.make_move(&mov).is_err() {
+                continue;
+            }
+            self.counters.nodes.fetch_add(1, Ordering::Relaxed);
+            let score = -self.quiescence(&child, -beta, -alpha);
+            if score > best {
+                best = score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+// Mates and other wins found at ply n from the root score WON - n for the winner, like mates
+fn outcome_score(outcome: Outcome, turn: Color, ply: i32) -> i32 {
+    match outcome {
+        Outcome::Win(color) if color == turn => WON - ply,
+        Outcome::Win(_) => -WON + ply,
+        Outcome::Draw => 0,
+    }
+}
+
+// Captures that win material go first, the best ones first, then the quiet moves in the order
+// they were generated and the captures that lose material last
+fn capture_order(game: &Game, mov: &Move, params: &EvalParams) -> i32 {
+    if !game.is_capture(mov) {
+        return 0;
+    }
+    let see = game.see(mov, params);
+    if see >= 0 {
+        see + 1
+    } else {
+        see
+    }
+}
+
+fn has_pieces(game: &Game, color: Color) -> bool {
+    Position::all().any(|pos| match game.get_piece(pos) {
+        Some(piece) => {
+            piece.color == color && piece.tipo != PieceType::Pawn && piece.tipo != PieceType::King
+        }
+        None => false,
+    })
+}
+
+fn move_to_front(moves: &mut [Move], mov: Move) {
+    if let Some(i) = moves.iter().position(|&m| m == mov) {
+        moves[..=i].rotate_right(1);
+    }
+}
+
+// Mate scores are stored relative to the node instead of the root, so they stay right when
+// the same position shows up at another ply
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= WON - MAX_PLY {
+        score + ply
+    } else if score <= -WON + MAX_PLY {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= WON - MAX_PLY {
+        score - ply
+    } else if score <= -WON + MAX_PLY {
+        score + ply
+    } else {
+        score
+    }
 }
 
 #[test]
-fn test_evaluate() {
-    let game: Game = Game::new();
-    assert_eq!(game.evaluate(), 0);
+fn test_search_result() {
+    let game =
+        Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+            .unwrap();
+    let mut depths = Vec::new();
+    let result = Engine::new().search(&game, 2, |result| depths.push(result.depth));
+    assert_eq!(depths, vec![1, 2]);
+    assert_eq!(result.best_move, Some(Move::safe_from_string("h5f7")));
+    assert_eq!(result.score, WonWhite(1));
+    assert_eq!(result.pv_string(&game), "h5f7");
+    assert!(result.nodes > 0);
+    assert_eq!(result.centipawns(Color::Black), -WON_CENTIPAWNS - 1);
+
+    let result = Engine::new().search(&Game::new(), 3, |_| {});
+    assert_eq!(result.mate_in(Color::White), None);
+    assert_eq!(result.pv.len(), 3);
+    assert!(result.score.is_valid());
 }
 
 // #[bench]
@@ -99,29 +874,251 @@ fn test_evaluate() {
 // #[bench]
 // fn bench_get_move(b: &mut test::Bencher) {
 //     let game: Game = Game::new();
-//     b.iter(|| get_move(&game, 3));
+//     b.iter(|| Engine::new().get_move(&game, 3));
 // }
 // #[bench]
 // fn bench_game(b: &mut test::Bencher) {
 //     b.iter(|| {
 //         let mut game: Game = Game::new();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
-//         let engine_move = get_move(&game, 3);
+//         let engine_move = Engine::new().get_move(&game, 3);
 //         game.make_move(&engine_move).unwrap();
 //     });
 // }
+
+#[test]
+fn test_mate_distance() {
+    // Ra8 mates at once, but a deeper search also finds slower mates
+    let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+    let result = Engine::new().search(&game, 3, |_| {});
+    assert_eq!(result.best_move, Some(Move::safe_from_string("a1a8")));
+    assert_eq!(result.score, WonWhite(1));
+    assert_eq!(result.mate_in(Color::White), Some(1));
+
+    let game = Game::from_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+    let result = Engine::new().search(&game, 3, |_| {});
+    assert_eq!(result.best_move, Some(Move::safe_from_string("a8b8")));
+    assert_eq!(result.score, WonWhite(2));
+    assert_eq!(result.mate_in(Color::Black), Some(-1));
+    assert_eq!(result.centipawns(Color::Black), -WON_CENTIPAWNS - 1);
+}
+
+#[test]
+fn test_stalemate() {
+    let game = Game::from_fen("k7/8/1Q6/8/8/8/8/7K b - - 0 1").unwrap();
+    let result = Engine::new().search(&game, 2, |_| {});
+    assert_eq!(result.best_move, None);
+    assert_eq!(result.score, Value(0));
+}
+
+#[test]
+fn test_book_variants() {
+    let mut entry = Game::new().polyglot_key().to_be_bytes().to_vec();
+    // e2e4 with weight 1
+    entry.extend_from_slice(&[0x03, 0x1c, 0, 1, 0, 0, 0, 0]);
+    let mut engine = Engine::new();
+    engine.set_book(Some(Book::from_bytes(&entry)));
+    let e4 = Some(Move::safe_from_string("e2e4"));
+    assert_eq!(engine.book_move(&Game::new()), e4);
+    for variant in ::variant::VARIANTS.iter().skip(1) {
+        assert_eq!(engine.book_move(&variant.start_position()), None);
+    }
+}
+
+#[test]
+fn test_castling_search() {
+    // Only castling brings the rook past the king on b1 to mate on the d file
+    let game = Game::from_fen("2rkr3/2p1p3/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+    let result = Engine::new().search(&game, 2, |_| {});
+    let castling = game.castling_move(false);
+    assert_eq!(result.best_move, Some(castling));
+    assert_eq!(result.score, WonWhite(1));
+    assert_eq!(game.format_move(&castling), "b1a1");
+}
+
+#[test]
+fn test_parallel_search() {
+    let mut engine = Engine::new();
+    engine.set_threads(4);
+    assert_eq!(engine.threads(), 4);
+    let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+    let result = engine.search(&game, 3, |_| {});
+    assert_eq!(result.best_move, Some(Move::safe_from_string("a1a8")));
+    assert_eq!(result.score, WonWhite(1));
+
+    engine.clear();
+    let result = engine.search(&Game::new(), 3, |_| {});
+    assert_eq!(result.pv.len(), 3);
+    assert!(result.score.is_valid());
+
+    // Helpers start deeper than the main thread, yet it still finishes its first iteration
+    // when there is no time at all
+    engine.set_threads(8);
+    let game = Game::from_fen(BENCH_POSITIONS[1]).unwrap();
+    for _ in 0..20 {
+        engine.clear();
+        let result = engine.search_limited(&game, SearchLimits::time(Duration::new(0, 0)), |_| {});
+        assert_eq!(result.depth, 1);
+        assert!(result.best_move.is_some());
+    }
+
+    engine.set_threads(0);
+    assert_eq!(engine.threads(), 1);
+}
+
+#[test]
+fn test_pruning() {
+    let mut engine = Engine::new();
+    // Tactics that the pruned search must still find: a mate on the back rank, a knight fork
+    // and a queen sacrifice that mates
+    let tactics = [
+        ("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1a8"),
+        ("r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1", "b5c7"),
+        (
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            "h5f7",
+        ),
+    ];
+    for &features in [SearchFeatures::none(), SearchFeatures::all()].iter() {
+        engine.set_features(features);
+        for &(fen, best) in tactics.iter() {
+            engine.clear();
+            let result = engine.get_move(&Game::from_fen(fen).unwrap(), 4);
+            assert_eq!(
+                result.best_move,
+                Some(Move::safe_from_string(best)),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    engine.set_features(SearchFeatures::none());
+    let (full, _) = engine.bench(3);
+    engine.set_features(SearchFeatures::all());
+    let (pruned, _) = engine.bench(3);
+    assert!(
+        pruned < full,
+        "{} nodes with pruning, {} without",
+        pruned,
+        full
+    );
+}
+
+#[test]
+fn test_windows() {
+    // Narrower windows only make the search cheaper, the score has to stay the same
+    let mut engine = Engine::new();
+    let mut windows = SearchFeatures::none();
+    windows.aspiration_windows = true;
+    windows.principal_variation_search = true;
+    for fen in BENCH_POSITIONS.iter() {
+        let game = Game::from_fen(fen).unwrap();
+        engine.set_features(SearchFeatures::none());
+        engine.clear();
+        let full = engine.get_move(&game, 4);
+        engine.set_features(windows);
+        engine.clear();
+        let narrow = engine.get_move(&game, 4);
+        assert_eq!(full.score, narrow.score, "{}", fen);
+    }
+}
+
+#[test]
+fn test_pvs_with_reductions() {
+    // With all the pruning on, the null windows have to save nodes rather than cost them in
+    // re-searches
+    let mut engine = Engine::new();
+    let (with_pvs, _) = engine.bench(4);
+    let mut features = SearchFeatures::all();
+    features.principal_variation_search = false;
+    engine.set_features(features);
+    let (without_pvs, _) = engine.bench(4);
+    assert!(with_pvs < without_pvs, "{} >= {}", with_pvs, without_pvs);
+}
+
+#[test]
+fn test_extensions() {
+    // Mates that end right past the horizon of a plain search at the given depth
+    let mates = [
+        ("k7/8/2K5/8/8/8/8/7R w - - 0 1", 3, WonWhite(3)),
+        ("4k3/8/8/8/8/8/R7/1R4K1 w - - 0 1", 3, WonWhite(3)),
+        ("7k/8/8/4K3/8/8/8/R7 w - - 0 1", 5, WonWhite(5)),
+        // Philidor's smothered mate, every white move is a check
+        ("r6k/6pp/8/4N3/2Q5/8/8/6K1 w - - 0 1", 3, WonWhite(7)),
+        (
+            "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+            3,
+            WonBlack(5),
+        ),
+    ];
+    let mut engine = Engine::new();
+    let mut extensions = SearchFeatures::none();
+    extensions.extensions = true;
+    for &(fen, depth, score) in mates.iter() {
+        let game = Game::from_fen(fen).unwrap();
+        engine.set_features(SearchFeatures::none());
+        engine.clear();
+        assert!(engine
+            .get_move(&game, depth)
+            .mate_in(Color::White)
+            .is_none());
+        engine.set_features(extensions);
+        engine.clear();
+        assert_eq!(engine.get_move(&game, depth).score, score, "{}", fen);
+    }
+}
+
+#[test]
+fn test_quiescence() {
+    // Taking the pawn looks good to a one ply search, but the queen gets taken back
+    let game = Game::from_fen("4k3/5p2/4p3/8/8/8/4Q3/4K3 w - - 0 1").unwrap();
+    let queen_takes = Move::safe_from_string("e2e6");
+    let mut engine = Engine::new();
+    engine.set_features(SearchFeatures::none());
+    assert_eq!(engine.get_move(&game, 1).best_move, Some(queen_takes));
+    engine.set_features(SearchFeatures::all());
+    engine.clear();
+    let result = engine.get_move(&game, 1);
+    assert!(result.best_move != Some(queen_takes));
+    assert!(result.score > Value(50));
+}
+
+#[test]
+fn test_clock_search() {
+    let engine = Engine::new();
+    let clock = Clock {
+        remaining: Duration::from_secs(60),
+        increment: Duration::from_secs(0),
+        moves_to_go: None,
+    };
+    // The only legal move gets played after the first iteration
+    let game = Game::from_fen("7k/8/8/8/8/8/6q1/7K w - - 0 1").unwrap();
+    let result = engine.search_limited(&game, SearchLimits::clock(clock), |_| {});
+    assert_eq!(result.best_move, Some(Move::safe_from_string("h1g2")));
+    assert_eq!(result.depth, 1);
+
+    // An almost empty clock still gives a move, and doesn't go over what's left
+    let clock = Clock {
+        remaining: Duration::from_millis(100),
+        ..clock
+    };
+    let result = engine.search_limited(&Game::new(), SearchLimits::clock(clock), |_| {});
+    assert!(result.best_move.is_some());
+    assert!(result.elapsed < Duration::from_millis(100));
+}
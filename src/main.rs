@@ -1,7 +1,30 @@
+extern crate rand;
+extern crate shakmaty;
+extern crate shakmaty_syzygy;
+
 use std::io;
 #[macro_use]
 mod game;
 mod lurri;
+mod fen;
+mod uci;
+mod zobrist;
+mod tt;
+mod book;
+mod tablebase;
+mod san;
+mod epd;
+mod selfplay;
+mod eval;
+mod tuner;
+mod chess960;
+mod variant;
+mod crazyhouse;
+mod render;
+mod diagram;
+mod referee;
+mod time;
+mod ponder;
 use game::*;
 use game::Color::{White, Black};
 
@@ -10,20 +33,28 @@ fn main() {
     println!("Write your moves like e2e4. You are white by default");
     let mut game: Game = Game::new();
     let mut engine_color = Black;
-    let engine_depth = 3;
+    let engine_depth = lurri::DEFAULT_DEPTH;
+    let mut engine = lurri::Engine::new();
+    let mut render = render::RenderOptions::default();
     loop {
-        game.show();
-        if game.turn == engine_color {
-            let engine_move: Move = lurri::get_move(&game, engine_depth);
-            match game.make_move(&engine_move) {
-                Err(ref e) => {
-                    println!("Lurri couldn't make move {}, {}", engine_move, e);
-                    continue;
-                },
-                Ok(_) => {
-                    println!("Lurri has moved");
-                    continue;
+        show(&game, &render, engine_color);
+        let outcome = game.outcome();
+        if let Some(outcome) = outcome { println!("Game over, {}", outcome) }
+        if game.turn == engine_color && outcome.is_none() {
+            let result = engine.get_move(&game, engine_depth);
+            let shown = show_result(&game, &result);
+            match result.best_move {
+                Some(engine_move) => match game.make_move(&engine_move) {
+                    Err(ref e) => {
+                        println!("Lurri couldn't make move {}, {}", engine_move, e);
+                        continue;
+                    },
+                    Ok(_) => {
+                        println!("Lurri has moved {}", shown);
+                        continue;
+                    },
                 },
+                None => println!("Lurri has no moves left"),
             }
         };
 
@@ -35,26 +66,118 @@ fn main() {
         line.pop(); // Remove the new line character
 
         match line.as_ref() {
-            "new"  => game = Game::new(),
+            "new"  => { game = Game::new(); engine.clear() },
+            "new960" => { game = Game::new_chess960(rand::random::<u32>() % chess960::POSITIONS).unwrap(); engine.clear() },
+            _ if line.starts_with("new960 ") => match line["new960 ".len()..].parse().ok().and_then(Game::new_chess960) {
+                Some(new_game) => { game = new_game; engine.clear() },
+                None => println!("Expected a start position number from 0 to {}", chess960::POSITIONS - 1),
+            },
+            _ if line.starts_with("variant ") => match variant::Variant::from_name(&line["variant ".len()..]) {
+                Some(variant) => { game = variant.start_position(); engine.clear() },
+                None => println!("Unknown variant, expected one of {}", variant::VARIANTS.iter().map(|v| v.name()).collect::<Vec<_>>().join(", ")),
+            },
             "quit" => { println!("Bye"); break },
             "white" => engine_color = White,
             "black" => engine_color = Black,
+            _ if ["unicode ", "colors ", "coordinates ", "highlight "].iter().any(|name| line.starts_with(name)) => {
+                let (name, value) = line.split_at(line.find(' ').unwrap());
+                let on = value.trim() == "on";
+                match name {
+                    "unicode" => render.unicode = on,
+                    "colors" => render.colors = on,
+                    "coordinates" => render.coordinates = on,
+                    _ => render.highlight = on,
+                }
+            },
+            // diagram <file.svg> [fen], the current position if no FEN is given
+            _ if line.starts_with("diagram ") => {
+                let (path, fen) = match line["diagram ".len()..].trim().find(' ') {
+                    Some(split) => line["diagram ".len()..].trim().split_at(split),
+                    None => (line["diagram ".len()..].trim(), ""),
+                };
+                let options = render::RenderOptions { orientation: !engine_color, ..render };
+                let svg = match fen.trim() {
+                    "" => Ok(game.svg(&options)),
+                    fen => diagram::svg_from_fen(fen, &options).map_err(|e| e.to_string()),
+                };
+                match svg.and_then(|svg| std::fs::write(path, svg).map_err(|e| e.to_string())) {
+                    Ok(_) => println!("Saved the diagram to {}", path),
+                    Err(e) => println!("Couldn't save the diagram, {}", e),
+                }
+            },
             "xboard" => return xboard(),
+            "uci" => return uci::uci(),
+            "fen" => println!("{}", game.to_fen()),
+            "shredderfen" => println!("{}", game.to_shredder_fen()),
+            "book off" => engine.set_book(None),
+            _ if line.starts_with("book ") => match book::Book::open(&line["book ".len()..]) {
+                Ok(book) => { println!("Loaded {} book entries", book.len()); engine.set_book(Some(book)) },
+                Err(e) => println!("Couldn't load book, {}", e),
+            },
+            _ if line.starts_with("bookdepth ") => match line["bookdepth ".len()..].parse() {
+                Ok(plies) => engine.set_book_depth(plies),
+                Err(e) => println!("Couldn't read book depth, {}", e),
+            },
+            _ if line.starts_with("syzygy ") => match tablebase::Tablebase::open(&line["syzygy ".len()..]) {
+                Ok(tb) => { println!("Loaded tablebases up to {} pieces", tb.max_pieces()); engine.set_tablebase(Some(tb)) },
+                Err(e) => println!("Couldn't load tablebases, {}", e),
+            },
+            "bench" => bench(&engine, engine_depth),
+            _ if line.starts_with("bench ") => match line["bench ".len()..].parse() {
+                Ok(depth) => bench(&engine, depth),
+                Err(e) => println!("Couldn't read depth, {}", e),
+            },
+            _ if line.starts_with("epd ") => run_epd(&engine, &line["epd ".len()..], engine_depth),
+            _ if line.starts_with("selfplay") => self_play(&line["selfplay".len()..], engine_depth),
+            _ if line.starts_with("referee ") => referee(&line["referee ".len()..]),
+            _ if line.starts_with("evalfile ") => match eval::EvalParams::load(&line["evalfile ".len()..]) {
+                Ok(params) => { print!("{}", params); engine.set_eval_params(params) },
+                Err(e) => println!("Couldn't load evaluation parameters, {}", e),
+            },
+            _ if line.starts_with("tune ") => tune(&engine, &line["tune ".len()..]),
+            "pruning on" => engine.set_features(lurri::SearchFeatures::all()),
+            "pruning off" => engine.set_features(lurri::SearchFeatures::none()),
+            _ if ["nullmove ", "lmr ", "aspiration ", "pvs ", "extensions ", "quiescence "].iter().any(|name| line.starts_with(name)) => {
+                let mut features = engine.features();
+                let (name, value) = line.split_at(line.find(' ').unwrap());
+                let on = value.trim() == "on";
+                match name {
+                    "nullmove" => features.null_move = on,
+                    "lmr" => features.late_move_reductions = on,
+                    "aspiration" => features.aspiration_windows = on,
+                    "extensions" => features.extensions = on,
+                    "quiescence" => features.quiescence = on,
+                    _ => features.principal_variation_search = on,
+                }
+                engine.set_features(features);
+                println!("{:?}", features);
+            },
+            _ if line.starts_with("threads ") => match line["threads ".len()..].parse() {
+                Ok(threads) => { engine.set_threads(threads); println!("Using {} threads", engine.threads()) },
+                Err(e) => println!("Couldn't read thread count, {}", e),
+            },
+            // A FEN with check counts is Three-check, any other keeps the current variant
+            _ if line.starts_with("setboard ") => match Game::from_fen_with_variant(&line["setboard ".len()..], game.variant) {
+                Ok(new_game) => game = new_game,
+                Err(e) => println!("Couldn't load position, {}", e),
+            },
             "l" => {
-                let engine_move: Move = lurri::get_move(&game, engine_depth);
-                match game.make_move(&engine_move) {
-                    Err(ref e) => {
+                let result = engine.get_move(&game, engine_depth);
+                let shown = show_result(&game, &result);
+                match result.best_move.map(|mov| game.make_move(&mov)) {
+                    Some(Err(ref e)) => {
                         println!("Lurri couldn't make move, {}", e)
                     },
-                    Ok(_) => {
-                        println!("Lurri has moved")
+                    Some(Ok(_)) => {
+                        println!("Lurri has moved {}", shown)
                     },
+                    None => println!("Lurri has no moves left"),
                 }
             },
-            _ => match Move::from_string(&line) {
+            _ => match game.parse_move(&line) {
                 Ok(ref mov) => {
                     match game.make_move(&mov) {
-                        Ok(_) => { println!("Move made"); game.show() },
+                        Ok(_) => { println!("Move made"); show(&game, &render, engine_color) },
                         Err(e) => println!("Couldn't make move, {}", e),
                     }
                 },
@@ -69,36 +192,143 @@ fn main() {
 fn xboard() {
     let mut game: Game = Game::new();
     let mut engine_color = Black;
-    let engine_depth = 3;
+    let engine_depth = lurri::DEFAULT_DEPTH;
+    let mut post = false;
+    let mut chess960 = false;
+    let mut variant = variant::Variant::Standard;
+    let mut force = false;
+    // Moves per session and increment from level, and what's left on the clock from time
+    let mut level = (0, std::time::Duration::from_secs(0));
+    let mut remaining = None;
+    let mut ponder = false;
+    // The reply expected to the engine's last move, which it thinks about on the opponent's time
+    let mut expected = None;
+    let mut input = ponder::Input::stdin();
+    let mut engine = lurri::Engine::new();
     loop {
-        if game.turn == engine_color {
+        if !force && game.turn == engine_color && game.rules().goal(&game).is_none() {
             println!("# lurri should think");
-            let engine_move: Move = lurri::get_move(&game, engine_depth);
-            match game.make_move(&engine_move) {
-                Ok(_) => {
-                    println!("move {}", engine_move);
-                },
-                Err(e) => {
-                    println!("# telluser error: {}", e)
+            let limits = match remaining {
+                Some(remaining) => lurri::SearchLimits::clock(time::Clock { remaining, increment: level.1, moves_to_go: time::moves_to_go(level.0, &game) }),
+                None => lurri::SearchLimits::depth(engine_depth),
+            };
+            let report = |result: &lurri::SearchResult| {
+                if post {
+                    println!("{} {} {} {} {}",
+                             result.depth,
+                             result.centipawns(game.turn),
+                             result.elapsed.as_millis() / 10,
+                             result.nodes,
+                             result.pv_string(&game));
+                }
+            };
+            // ? asks for the move right now, everything else waits for it
+            let result = input.search_in_background(&engine, &game, limits, report, |line| {
+                if line == "?" { ponder::Reaction::Stop } else { ponder::Reaction::Defer }
+            });
+            expected = ponder::expected_reply(&game, &result);
+            match result.best_move {
+                Some(engine_move) => {
+                    // xboard writes castling as O-O in Chess960
+                    let notation = match engine_move.tipo {
+                        MoveType::ShortCastling | MoveType::LongCastling if game.chess960 => engine_move.to_string(),
+                        _ => game.format_move(&engine_move),
+                    };
+                    match game.make_move(&engine_move) {
+                        Ok(_) => {
+                            println!("move {}", notation);
+                            announce_goal(&game);
+                        },
+                        Err(e) => {
+                            println!("# telluser error: {}", e)
+                        },
+                    }
                 },
+                None => println!("# lurri has no moves left"),
             }
         };
-        let mut line: String = String::new();
-        if let Err(..) = io::stdin().read_line(&mut line) {
-            println!("# failed to read input");
-            continue;
-        }; line.pop();
+        // Not when the opponent's move is already waiting
+        if ponder && !force && game.turn != engine_color && !input.has_pending() {
+            if let Some(reply) = expected.take() {
+                let mut guess = game.clone();
+                if guess.make_move(&reply).is_ok() && guess.rules().goal(&guess).is_none() {
+                    println!("# pondering on {}", reply);
+                    // The clocks come right before the opponent's move, which ends the pondering.
+                    // On a hit the table is full of the position the engine has to play
+                    input.search_in_background(&engine, &guess, lurri::SearchLimits::depth(lurri::MAX_DEPTH), |_| {}, |line| {
+                        if line.starts_with("time ") || line.starts_with("otim ") || line == "hard" { ponder::Reaction::Defer } else { ponder::Reaction::Postpone }
+                    });
+                }
+            }
+        }
+        let line = match input.next_line() {
+            Some(line) => line,
+            None => break,
+        };
 
         match line.as_ref() {
-            "new"  => game = Game::new(),
+            "new"  => { game = Game::new(); engine_color = Black; force = false; remaining = None; expected = None; chess960 = false; variant = variant::Variant::Standard; engine.clear() },
+            "force" => force = true,
+            "hard" => ponder = true,
+            "easy" => ponder = false,
+            "?" => {},
+            "go" => { engine_color = game.turn; force = false },
+            "variant fischerandom" => { chess960 = true; game.chess960 = true },
+            _ if line.starts_with("variant ") => match variant::Variant::from_name(&line["variant ".len()..]) {
+                Some(new_variant) => { variant = new_variant; game = Game { chess960, ..variant.start_position() }; engine.clear() },
+                None => println!("Error (unsupported variant): {}", line),
+            },
             "white" => engine_color = White,
             "black" => engine_color = Black,
             "quit" => break,
-            "edit" => edit_mode(&mut game),
-            _ => match Move::from_string(&line) {
+            "edit" => edit_mode(&mut game, &mut input),
+            "post" => post = true,
+            "nopost" => post = false,
+            _ if line.starts_with("protover ") => {
+                let mut variants: Vec<&str> = variant::VARIANTS.iter().map(|v| v.name()).collect();
+                variants.extend(variant::ALIASES.iter().map(|&(alias, _)| alias));
+                println!("feature myname=\"Lurri\" smp=1 egt=\"syzygy\" variants=\"{},fischerandom\" option=\"Book File -file \" option=\"Book Depth -spin 20 0 1000\" done=1", variants.join(","))
+            },
+            _ if line.starts_with("option Book File=") => match &line["option Book File=".len()..] {
+                "" => engine.set_book(None),
+                path => match book::Book::open(path) {
+                    Ok(book) => engine.set_book(Some(book)),
+                    Err(e) => println!("tellusererror Couldn't load book, {}", e),
+                },
+            },
+            _ if line.starts_with("option Book Depth=") => match line["option Book Depth=".len()..].parse() {
+                Ok(plies) => engine.set_book_depth(plies),
+                Err(e) => println!("# Error ({}): {}", e, line),
+            },
+            _ if line.starts_with("egtpath syzygy ") => match tablebase::Tablebase::open(&line["egtpath syzygy ".len()..]) {
+                Ok(tb) => engine.set_tablebase(Some(tb)),
+                Err(e) => println!("tellusererror Couldn't load tablebases, {}", e),
+            },
+            _ if line.starts_with("level ") => match time::parse_level(&line["level ".len()..]) {
+                Some((moves, _, increment)) => level = (moves, increment),
+                None => println!("# Error (bad level): {}", line),
+            },
+            // Both clocks come in centiseconds, only ours matters
+            _ if line.starts_with("time ") => match line["time ".len()..].parse::<u64>() {
+                Ok(centiseconds) => remaining = Some(std::time::Duration::from_millis(centiseconds * 10)),
+                Err(e) => println!("# Error ({}): {}", e, line),
+            },
+            _ if line.starts_with("otim ") => {},
+            _ if line.starts_with("cores ") => match line["cores ".len()..].parse() {
+                Ok(cores) => engine.set_threads(cores),
+                Err(e) => println!("# Error ({}): {}", e, line),
+            },
+            _ if line.starts_with("setboard ") => match Game::from_fen_with_variant(&line["setboard ".len()..], variant) {
+                Ok(new_game) => {
+                    game = new_game;
+                    game.chess960 |= chess960;
+                },
+                Err(e) => println!("tellusererror {}", e),
+            },
+            _ => match game.parse_move(&line) {
                 Ok(mov) => {
                     match game.make_move(&mov) {
-                        Ok(_) => println!("# telluser You made a move"),
+                        Ok(_) => { println!("# telluser You made a move"); announce_goal(&game) },
                         Err(e) => println!("# Illegal move ({}): {}", e, mov),
                     }
                 },
@@ -110,22 +340,168 @@ fn xboard() {
     }
 }
 
-fn edit_mode(game: &mut Game) {
+// The board is always seen from the side of the human
+fn show(game: &Game, render: &render::RenderOptions, engine_color: Color) {
+    print!("{}", game.render(&render::RenderOptions { orientation: !engine_color, ..*render }));
+}
+
+// xboard spots mates by itself but not the goals of the variants
+fn announce_goal(game: &Game) {
+    match game.rules().goal(game) {
+        Some(outcome @ variant::Outcome::Win(White)) => println!("1-0 {{{}}}", outcome),
+        Some(outcome @ variant::Outcome::Win(Black)) => println!("0-1 {{{}}}", outcome),
+        Some(outcome) => println!("1/2-1/2 {{{}}}", outcome),
+        None => {},
+    }
+}
+
+fn bench(engine: &lurri::Engine, depth: u32) {
+    let (nodes, elapsed) = engine.bench(depth);
+    let millis = elapsed.as_millis().max(1) as u64;
+    println!("{:?}", engine.features());
+    println!("{} positions at depth {}: {} nodes, {} ms, {} nps",
+             lurri::BENCH_POSITIONS.len(), depth, nodes, millis, nodes * 1000 / millis);
+}
+
+// epd <file> [depth N | time ms]
+fn run_epd(engine: &lurri::Engine, args: &str, default_depth: u32) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (path, rest) = match args.split_first() {
+        Some(split) => split,
+        None => { println!("Usage: epd <file> [depth N | time ms]"); return },
+    };
+    let limits = match rest {
+        [] => lurri::SearchLimits::depth(default_depth),
+        ["depth", n] => match n.parse() {
+            Ok(depth) => lurri::SearchLimits::depth(depth),
+            Err(e) => { println!("Couldn't read depth, {}", e); return },
+        },
+        ["time", n] => match n.parse() {
+            Ok(millis) => lurri::SearchLimits::time(std::time::Duration::from_millis(millis)),
+            Err(e) => { println!("Couldn't read time, {}", e); return },
+        },
+        _ => { println!("Usage: epd <file> [depth N | time ms]"); return },
+    };
+    match epd::load(path) {
+        Ok(suite) => epd::run(engine, &suite, limits),
+        Err(e) => println!("Couldn't load {}, {}", path, e),
+    }
+}
+
+fn self_play(args: &str, default_depth: u32) {
+    let (first, second, config) = match selfplay::parse_match(args, default_depth) {
+        Ok(settings) => settings,
+        Err(e) => { println!("{}", e); return },
+    };
+    println!("First:  {:?}", first);
+    println!("Second: {:?}", second);
+    let result = selfplay::play_match(&first, &second, &config, |i, color, result, stats| {
+        let outcome = match result.winner {
+            None => "draw".to_string(),
+            Some(winner) if winner == color => "first wins".to_string(),
+            Some(_) => "second wins".to_string(),
+        };
+        print!("Game {} (first is {:?}): {} by {} in {} plies, {}", i, color, outcome, result.termination, result.plies, stats);
+        if let Some(sprt) = config.sprt {
+            let (lower, upper) = sprt.bounds();
+            print!(", LLR {:.2} ({:.2}, {:.2})", stats.llr(sprt.elo0, sprt.elo1), lower, upper);
+        }
+        println!();
+    });
+    match result {
+        Ok(stats) => {
+            println!("Result after {} games: {}", stats.games(), stats);
+            if let Some(sprt) = config.sprt {
+                match sprt.decision(&stats) {
+                    Some(true) => println!("SPRT: H1 accepted, Elo >= {}", sprt.elo1),
+                    Some(false) => println!("SPRT: H0 accepted, Elo <= {}", sprt.elo0),
+                    None => println!("SPRT: inconclusive"),
+                }
+            }
+        },
+        Err(e) => println!("Match aborted, {}", e),
+    }
+}
+
+// referee [games=N] [maxplies=N] [tc=base+inc] [pgn=<file>] <engine> vs <engine>
+fn referee(args: &str) {
+    let (first, second, config) = match referee::parse_referee(args) {
+        Ok(settings) => settings,
+        Err(e) => { println!("{}", e); return },
+    };
+    println!("First:  {:?}", first);
+    println!("Second: {:?}", second);
+    let result = referee::run_match(&first, &second, &config, |i, color, record, stats| {
+        let outcome = match record.result.winner {
+            None => "draw".to_string(),
+            Some(winner) if winner == color => "first wins".to_string(),
+            Some(_) => "second wins".to_string(),
+        };
+        println!("Game {} ({} vs {}): {} by {} in {} plies, {}", i, record.white, record.black, outcome, record.result.termination, record.result.plies, stats);
+    });
+    match result {
+        Ok(stats) => println!("Result after {} games: {}", stats.games(), stats),
+        Err(e) => println!("Match aborted, {}", e),
+    }
+}
+
+// tune <positions file> [output file], starting from the engine's parameters
+fn tune(engine: &lurri::Engine, args: &str) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let path = match args.first() {
+        Some(path) => path,
+        None => { println!("Usage: tune <positions file> [output file]"); return },
+    };
+    let samples = match tuner::load_samples(path) {
+        Ok(samples) => samples,
+        Err(e) => { println!("Couldn't load positions, {}", e); return },
+    };
+    let params = engine.eval_params();
+    let k = tuner::fit_k(&samples, &params);
+    println!("{} positions, K {:.4}, error {:.6}", samples.len(), k, tuner::error(&samples, &params, k));
+    let tuned = tuner::tune(&samples, &params, k, 10_000, |pass, params, error| {
+        println!("Pass {}: error {:.6}, {:?}", pass, error, params.values())
+    });
+    print!("{}", tuned);
+    if let Some(path) = args.get(1) {
+        match tuned.save(path) {
+            Ok(()) => println!("Saved to {}", path),
+            Err(e) => println!("Couldn't save parameters, {}", e),
+        }
+    }
+}
+
+// Takes the position the search started from, the PV is written the way the engine plays it
+fn show_result(game: &Game, result: &lurri::SearchResult) -> String {
+    format!("(depth {}, score {:?}, {} nodes, {} nps, {} tb hits, pv {})",
+            result.depth, result.score, result.nodes, result.nps(), result.tb_hits, result.pv_string(game))
+}
+
+fn edit_mode(game: &mut Game, input: &mut ponder::Input) {
     let mut curr_color = White;
-    loop {
-        let mut line: String = String::new();
-        if let Err(..) = io::stdin().read_line(&mut line) {
-            continue;
-        }; line.pop();
+    let mut edited = game.clone();
+    while let Some(line) = input.next_line() {
 
         match line.as_ref() {
             "c"  => curr_color = ! curr_color,
-            "." => break,
-            _ => match Move::from_string(&line) {
-                Ok(ref mov) => {
-                    match game.make_move(&mov) {
-                        Ok(()) => println!("telluser You made a move"),
-                        Err(e) => println!("Illegal move ({}): {}", e, mov),
+            "#" => edited.clear(),
+            "." => {
+                edited.reset_castling_rights();
+                edited.en_passant = None;
+                let problems = edited.validate();
+                if problems.is_empty() {
+                    *game = edited;
+                } else {
+                    let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                    println!("tellusererror Illegal position: {}", problems.join(", "));
+                }
+                break;
+            },
+            _ => match parse_placement(&line) {
+                Ok((piece_type, pos)) => {
+                    let piece = piece_type.map(|pt| Piece::new(curr_color, pt));
+                    if let Err(e) = edited.set_square(pos, piece) {
+                        println!("Error ({}): {}", e, line)
                     }
                 },
                 Err(ref e) => {
@@ -135,3 +511,16 @@ fn edit_mode(game: &mut Game) {
         }
     }
 }
+
+// Parses xboard's edit mode placements, like "Pe4", or "xe4" to empty the square
+fn parse_placement(s: &str) -> Result<(Option<PieceType>, Position), ParseError> {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() != 3 {
+        return Err(ParseError::MalformedMove);
+    }
+    let piece_type = match chars[0].to_ascii_lowercase() {
+        'x' => None,
+        c => Some(PieceType::from_char(c)?),
+    };
+    Ok((piece_type, Position::from_chars(chars[1], chars[2])?))
+}
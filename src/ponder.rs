@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use game::*;
+use lurri;
+
+// How often a background search gets checked for having finished while no input comes
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+// What a line that arrives during a background search does to it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Reaction {
+    // The line was handled and the search goes on
+    Continue,
+    // The search goes on and the line waits until it's over
+    Defer,
+    // The line was handled by stopping the search
+    Stop,
+    // The search stops and the line gets handled after it
+    Postpone,
+}
+
+// The protocol input. A thread reads stdin so that lines keep coming while the engine searches
+pub struct Input {
+    lines: Receiver<String>,
+    // Lines that arrived during a search and are still waiting to be handled
+    pending: VecDeque<String>,
+}
+
+impl Input {
+    pub fn stdin() -> Input {
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        Input::from_channel(lines)
+    }
+
+    pub fn from_channel(lines: Receiver<String>) -> Input {
+        Input {
+            lines,
+            pending: VecDeque::new(),
+        }
+    }
+
+    // None once the input is closed
+    pub fn next_line(&mut self) -> Option<String> {
+        self.pending.pop_front().or_else(|| self.lines.recv().ok())
+    }
+
+    // Whether next_line has something to return without waiting
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Puts a line back so that next_line returns it again
+    pub fn unread(&mut self, line: String) {
+        self.pending.push_front(line);
+    }
+
+    // Searches in another thread while handle decides what the lines read meanwhile do. The
+    // search also stops when the input gets closed
+    pub fn search_in_background<R, H>(
+        &mut self,
+        engine: &lurri::Engine,
+        game: &Game,
+        limits: lurri::SearchLimits,
+        report: R,
+        mut handle: H,
+    ) -> lurri::SearchResult
+    where
+        R: FnMut(&lurri::SearchResult) + Send,
+        H: FnMut(&str) -> Reaction,
+    {
+        let interrupt = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let search =
+                scope.spawn(|| engine.search_interruptible(game, limits, &interrupt, report));
+            while !search.is_finished() {
+                let line = match self.lines.recv_timeout(POLL_INTERVAL) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        interrupt.store(true, Ordering::Relaxed);
+                        thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                };
+                let reaction = handle(&line);
+                if reaction == Reaction::Stop || reaction == Reaction::Postpone {
+                    interrupt.store(true, Ordering::Relaxed);
+                }
+                if reaction == Reaction::Defer || reaction == Reaction::Postpone {
+                    self.pending.push_back(line);
+                }
+            }
+            search.join().expect("The search thread panicked")
+        })
+    }
+}
+
+// The move the opponent is expected to answer the best move with, from the principal variation
+pub fn expected_reply(game: &Game, result: &lurri::SearchResult) -> Option<Move> {
+    let (&best, &reply) = match result.pv[..] {
+        [ref best, ref reply, ..] => (best, reply),
+        _ => return None,
+    };
+    let mut after = game.clone();
+    if result.best_move != Some(best) || after.make_move(&best).is_err() {
+        return None;
+    }
+    Some(reply).filter(|reply| after.legal_moves().contains(reply))
+}
+
+#[test]
+fn test_expected_reply() {
+    let game = Game::new();
+    let result = lurri::Engine::new().get_move(&game, 3);
+    let reply = expected_reply(&game, &result).unwrap();
+    assert_eq!(Some(reply), result.pv.get(1).cloned());
+
+    let mut short = result.clone();
+    short.pv.truncate(1);
+    assert_eq!(expected_reply(&game, &short), None);
+    let mut illegal = result.clone();
+    illegal.pv[1] = Move::safe_from_string("e2e4");
+    assert_eq!(expected_reply(&game, &illegal), None);
+}
+
+#[test]
+fn test_search_in_background() {
+    let (sender, lines) = channel();
+    let mut input = Input::from_channel(lines);
+    let engine = lurri::Engine::new();
+    let game = Game::new();
+    let infinite = lurri::SearchLimits::depth(lurri::MAX_DEPTH);
+    sender.send("isready".to_string()).unwrap();
+    sender.send("time 100".to_string()).unwrap();
+    sender.send("stop".to_string()).unwrap();
+    sender.send("quit".to_string()).unwrap();
+    let mut handled = Vec::new();
+    let result = input.search_in_background(
+        &engine,
+        &game,
+        infinite,
+        |_| {},
+        |line| {
+            handled.push(line.to_string());
+            match line {
+                "isready" => Reaction::Continue,
+                "stop" => Reaction::Stop,
+                _ => Reaction::Defer,
+            }
+        },
+    );
+    assert!(result.best_move.is_some());
+    assert!(result.depth < lurri::MAX_DEPTH);
+    // The line after stop may or may not arrive before the search is over
+    assert_eq!(handled[..3], ["isready", "time 100", "stop"]);
+    assert_eq!(input.next_line(), Some("time 100".to_string()));
+    assert_eq!(input.next_line(), Some("quit".to_string()));
+
+    // A closed input stops the search too
+    drop(sender);
+    let result =
+        input.search_in_background(&engine, &game, infinite, |_| {}, |_| Reaction::Continue);
+    assert!(result.best_move.is_some());
+    assert_eq!(input.next_line(), None);
+}
+
+#[test]
+fn test_interrupt_parallel_search() {
+    // The helpers start deeper than the main thread, a stop before it finishes depth 1 must
+    // still leave a move
+    let mut engine = lurri::Engine::new();
+    engine.set_threads(8);
+    let game = Game::from_fen(lurri::BENCH_POSITIONS[1]).unwrap();
+    let infinite = lurri::SearchLimits::depth(lurri::MAX_DEPTH);
+    for _ in 0..20 {
+        let (sender, lines) = channel();
+        let mut input = Input::from_channel(lines);
+        sender.send("stop".to_string()).unwrap();
+        engine.clear();
+        let result =
+            input.search_in_background(&engine, &game, infinite, |_| {}, |_| Reaction::Stop);
+        assert!(result.best_move.is_some());
+    }
+}
@@ -0,0 +1,745 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use game::Color::{Black, White};
+use game::PieceType::King;
+use game::*;
+use selfplay::{termination, GameResult, MatchStats, Termination, DEFAULT_MAX_PLIES, OPENINGS};
+
+// Engines get this long to answer everything but their moves
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+// xboard engines that don't know protover 2 never send their features
+const FEATURE_TIMEOUT: Duration = Duration::from_secs(2);
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+// Lines of movetext in the PGN don't go over this
+const PGN_LINE_LENGTH: usize = 80;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Protocol {
+    Uci,
+    Xboard,
+}
+
+// An engine executable and the protocol it speaks
+#[derive(Debug, PartialEq, Clone)]
+pub struct EngineSpec {
+    pub protocol: Protocol,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+impl EngineSpec {
+    // Reads "uci:<command>" or "xboard:<command>"
+    pub fn parse(s: &str) -> Result<EngineSpec, String> {
+        let (protocol, command) = match s.find(':') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => {
+                return Err(format!(
+                    "Expected uci:<command> or xboard:<command>, got {}",
+                    s
+                ))
+            }
+        };
+        let protocol = match protocol {
+            "uci" => Protocol::Uci,
+            "xboard" => Protocol::Xboard,
+            _ => return Err(format!("Unknown protocol {}", protocol)),
+        };
+        if command.is_empty() {
+            return Err(format!("Missing the command of {}", s));
+        }
+        Ok(EngineSpec {
+            protocol,
+            command: command.to_string(),
+            args: Vec::new(),
+        })
+    }
+}
+
+// The time each player starts with and gets after every move
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    // Reads seconds as "base+increment", like 60+0.5, or just "base"
+    pub fn parse(s: &str) -> Result<TimeControl, String> {
+        let seconds = |n: &str| {
+            n.parse::<f64>()
+                .ok()
+                .filter(|&n| n >= 0.0)
+                .map(|n| Duration::from_millis((n * 1000.0) as u64))
+                .ok_or_else(|| format!("Bad time control {}", s))
+        };
+        let (base, increment) = match s.find('+') {
+            Some(i) => (seconds(&s[..i])?, seconds(&s[i + 1..])?),
+            None => (seconds(s)?, Duration::from_secs(0)),
+        };
+        if base == Duration::from_secs(0) {
+            return Err(format!("Bad time control {}", s));
+        }
+        Ok(TimeControl { base, increment })
+    }
+}
+
+// Written the way PGN's TimeControl tag wants it
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.base.as_millis() as f64 / 1000.0)?;
+        if self.increment > Duration::from_secs(0) {
+            write!(f, "+{}", self.increment.as_millis() as f64 / 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RefereeConfig {
+    pub games: u32,
+    pub max_plies: u32,
+    pub time_control: TimeControl,
+    // Every game gets appended to this file
+    pub pgn: Option<String>,
+}
+
+impl RefereeConfig {
+    pub fn new(games: u32) -> RefereeConfig {
+        RefereeConfig {
+            games,
+            max_plies: DEFAULT_MAX_PLIES,
+            time_control: TimeControl {
+                base: Duration::from_secs(10),
+                increment: Duration::from_millis(100),
+            },
+            pgn: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Failure {
+    Timeout,
+    Disconnected,
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Move(String),
+    Resign,
+}
+
+struct EngineProcess {
+    protocol: Protocol,
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    // Moves of the current game the engine knows about, its own included
+    sent: usize,
+    // xboard engines leave force mode with their first go and answer every move after it
+    playing: bool,
+}
+
+impl EngineProcess {
+    fn start(spec: &EngineSpec) -> Result<EngineProcess, String> {
+        let mut child = Command::new(&spec.command)
+            .args(&spec.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Couldn't start {}, {}", spec.command, e))?;
+        let stdin = child.stdin.take().expect("The engine has a piped stdin");
+        let stdout = child.stdout.take().expect("The engine has a piped stdout");
+        // A thread reads the output so that waiting for it can time out
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        let name = Path::new(&spec.command)
+            .file_name()
+            .map_or(spec.command.clone(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        let mut engine = EngineProcess {
+            protocol: spec.protocol,
+            name,
+            child,
+            stdin,
+            lines,
+            sent: 0,
+            playing: false,
+        };
+        engine
+            .handshake()
+            .map_err(|e| format!("{} failed the handshake ({:?})", spec.command, e))?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), Failure> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| Failure::Disconnected)
+    }
+
+    fn read_line(&self, deadline: Instant) -> Result<String, Failure> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => Failure::Timeout,
+            RecvTimeoutError::Disconnected => Failure::Disconnected,
+        })
+    }
+
+    fn wait_for(&self, deadline: Instant, first_token: &str) -> Result<String, Failure> {
+        loop {
+            let line = self.read_line(deadline)?;
+            if line.split_whitespace().next() == Some(first_token) {
+                return Ok(line);
+            }
+        }
+    }
+
+    fn handshake(&mut self) -> Result<(), Failure> {
+        match self.protocol {
+            Protocol::Uci => {
+                self.send("uci")?;
+                let deadline = Instant::now() + RESPONSE_TIMEOUT;
+                loop {
+                    let line = self.read_line(deadline)?;
+                    if let Some(name) = line.strip_prefix("id name ") {
+                        self.name = name.trim().to_string();
+                    } else if line.trim() == "uciok" {
+                        break;
+                    }
+                }
+                self.send("isready")?;
+                self.wait_for(Instant::now() + RESPONSE_TIMEOUT, "readyok")?;
+            }
+            Protocol::Xboard => {
+                self.send("xboard")?;
+                self.send("protover 2")?;
+                let deadline = Instant::now() + FEATURE_TIMEOUT;
+                loop {
+                    let line = match self.read_line(deadline) {
+                        Ok(line) => line,
+                        Err(Failure::Timeout) => break,
+                        Err(e) => return Err(e),
+                    };
+                    if !line.starts_with("feature ") {
+                        continue;
+                    }
+                    if let Some(i) = line.find("myname=\"") {
+                        let rest = &line[i + "myname=\"".len()..];
+                        if let Some(end) = rest.find('"') {
+                            self.name = rest[..end].to_string();
+                        }
+                    }
+                    if line.contains("done=1") {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn new_game(&mut self, time_control: &TimeControl) -> Result<(), Failure> {
+        self.sent = 0;
+        self.playing = false;
+        match self.protocol {
+            Protocol::Uci => {
+                self.send("ucinewgame")?;
+                self.send("isready")?;
+                self.wait_for(Instant::now() + RESPONSE_TIMEOUT, "readyok")?;
+            }
+            Protocol::Xboard => {
+                let base = time_control.base.as_secs();
+                self.send("new")?;
+                self.send("force")?;
+                self.send(&format!(
+                    "level 0 {}:{:02} {}",
+                    base / 60,
+                    base % 60,
+                    time_control.increment.as_millis() as f64 / 1000.0
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Asks for a move after the given ones from the start position, and measures how long
+    // the engine takes to answer. The engine can't take longer than its clock
+    fn request_move(
+        &mut self,
+        moves: &[String],
+        clocks: [Duration; 2],
+        time_control: &TimeControl,
+        turn: Color,
+    ) -> Result<(Reply, Duration), Failure> {
+        let own = clocks[turn.index()];
+        let start = match self.protocol {
+            Protocol::Uci => {
+                if moves.is_empty() {
+                    self.send("position startpos")?;
+                } else {
+                    self.send(&format!("position startpos moves {}", moves.join(" ")))?;
+                }
+                let increment = time_control.increment.as_millis();
+                self.send(&format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    clocks[White.index()].as_millis(),
+                    clocks[Black.index()].as_millis(),
+                    increment,
+                    increment
+                ))?;
+                Instant::now()
+            }
+            Protocol::Xboard => {
+                self.send(&format!("time {}", own.as_millis() / 10))?;
+                self.send(&format!(
+                    "otim {}",
+                    clocks[(!turn).index()].as_millis() / 10
+                ))?;
+                for mov in moves[self.sent..].iter() {
+                    self.send(mov)?;
+                }
+                if !self.playing {
+                    self.send("go")?;
+                    self.playing = true;
+                }
+                Instant::now()
+            }
+        };
+        let deadline = start + own;
+        let reply = match self.protocol {
+            Protocol::Uci => {
+                let line = self.wait_for(deadline, "bestmove")?;
+                Reply::Move(line.split_whitespace().nth(1).unwrap_or("").to_string())
+            }
+            Protocol::Xboard => loop {
+                let line = self.read_line(deadline)?;
+                let mut tokens = line.split_whitespace();
+                match tokens.next() {
+                    Some("move") => break Reply::Move(tokens.next().unwrap_or("").to_string()),
+                    Some("resign") => break Reply::Resign,
+                    _ => {}
+                }
+            },
+        };
+        self.sent = moves.len() + 1;
+        Ok((reply, start.elapsed()))
+    }
+
+    fn end_game(&mut self, result: &GameResult) {
+        if self.protocol == Protocol::Xboard {
+            let _ = self.send(&format!(
+                "result {} {{{}}}",
+                result_tag(result.winner),
+                describe(result)
+            ));
+        }
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// A finished game, the moves start from the standard start position and include the opening
+#[derive(Debug, PartialEq, Clone)]
+pub struct GameRecord {
+    pub white: String,
+    pub black: String,
+    pub moves: Vec<Move>,
+    pub result: GameResult,
+}
+
+// Engines can write their moves in coordinate notation or in SAN
+fn read_move(game: &Game, text: &str) -> Option<Move> {
+    let legal = game.legal_moves();
+    game.parse_move(text)
+        .ok()
+        .filter(|mov| legal.contains(mov))
+        .or_else(|| game.parse_san(text).ok().filter(|mov| legal.contains(mov)))
+}
+
+// Running out of time only loses when the opponent has something left to mate with
+fn time_forfeit(game: &Game, turn: Color) -> GameResult {
+    let bare_king = Position::all()
+        .filter_map(|pos| game.get_piece(pos))
+        .all(|piece| piece.color == turn || piece.tipo == King);
+    GameResult {
+        winner: if bare_king { None } else { Some(!turn) },
+        termination: Termination::TimeForfeit,
+        plies: 0,
+    }
+}
+
+fn play_game(
+    white: &mut EngineProcess,
+    black: &mut EngineProcess,
+    opening: &str,
+    config: &RefereeConfig,
+) -> Result<GameRecord, String> {
+    let mut game = Game::new();
+    let mut moves = Vec::new();
+    // What the engines get told, in coordinate notation
+    let mut notation = Vec::new();
+    for token in opening.split_whitespace() {
+        let mov = game
+            .parse_move(token)
+            .map_err(|e| format!("{} ({})", e, token))?;
+        notation.push(game.format_move(&mov));
+        game.make_move(&mov)
+            .map_err(|e| format!("{} ({})", e, token))?;
+        moves.push(mov);
+    }
+    let time_control = config.time_control;
+    for engine in [&mut *white, &mut *black].iter_mut() {
+        engine
+            .new_game(&time_control)
+            .map_err(|e| format!("{} couldn't start a game ({:?})", engine.name, e))?;
+    }
+    let mut clocks = [time_control.base; 2];
+    let mut seen = HashMap::new();
+    let mut plies = 0;
+    let result = loop {
+        *seen.entry(game.hash()).or_insert(0) += 1;
+        if let Some(result) = termination(&game, &seen) {
+            break result;
+        }
+        if plies >= config.max_plies {
+            break GameResult {
+                winner: None,
+                termination: Termination::MaxPlies,
+                plies,
+            };
+        }
+        let turn = game.turn;
+        let lost = |termination| GameResult {
+            winner: Some(!turn),
+            termination,
+            plies,
+        };
+        let engine = match turn {
+            White => &mut *white,
+            Black => &mut *black,
+        };
+        let (reply, elapsed) = match engine.request_move(&notation, clocks, &time_control, turn) {
+            Ok(answer) => answer,
+            Err(Failure::Timeout) => break time_forfeit(&game, turn),
+            Err(Failure::Disconnected) => break lost(Termination::Disconnection),
+        };
+        let clock = &mut clocks[turn.index()];
+        if elapsed > *clock {
+            break time_forfeit(&game, turn);
+        }
+        *clock = *clock - elapsed + time_control.increment;
+        let mov = match reply {
+            Reply::Resign => break lost(Termination::Resignation),
+            Reply::Move(text) => match read_move(&game, &text) {
+                Some(mov) => mov,
+                None => break lost(Termination::IllegalMove),
+            },
+        };
+        notation.push(game.format_move(&mov));
+        game.make_move(&mov)
+            .map_err(|e| format!("{} ({} in {})", e, mov, game.to_fen()))?;
+        moves.push(mov);
+        plies += 1;
+    };
+    let result = GameResult { plies, ..result };
+    white.end_game(&result);
+    black.end_game(&result);
+    Ok(GameRecord {
+        white: white.name.clone(),
+        black: black.name.clone(),
+        moves,
+        result,
+    })
+}
+
+fn result_tag(winner: Option<Color>) -> &'static str {
+    match winner {
+        Some(White) => "1-0",
+        Some(Black) => "0-1",
+        None => "1/2-1/2",
+    }
+}
+
+fn describe(result: &GameResult) -> String {
+    match result.winner {
+        Some(winner) => format!("{} wins by {}", winner, result.termination),
+        None => format!("Draw by {}", result.termination),
+    }
+}
+
+pub fn pgn(record: &GameRecord, round: u32, time_control: &TimeControl) -> String {
+    let termination = match record.result.termination {
+        Termination::TimeForfeit => "time forfeit",
+        Termination::IllegalMove | Termination::Disconnection => "rules infraction",
+        Termination::MaxPlies => "adjudication",
+        _ => "normal",
+    };
+    let result = result_tag(record.result.winner);
+    let mut out = String::new();
+    for &(tag, ref value) in [
+        ("Event", "Lurri referee match".to_string()),
+        ("Site", "?".to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", round.to_string()),
+        ("White", record.white.clone()),
+        ("Black", record.black.clone()),
+        ("Result", result.to_string()),
+        ("TimeControl", time_control.to_string()),
+        ("Termination", termination.to_string()),
+    ]
+    .iter()
+    {
+        out.push_str(&format!("[{} \"{}\"]\n", tag, value.replace('"', "'")));
+    }
+    out.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut game = Game::new();
+    for (i, mov) in record.moves.iter().enumerate() {
+        if i % 2 == 0 {
+            tokens.push(format!("{}.", i / 2 + 1));
+        }
+        tokens.push(game.format_san(mov));
+        if game.make_move(mov).is_err() {
+            break;
+        }
+    }
+    tokens.push(format!("{{{}}}", describe(&record.result)));
+    tokens.push(result.to_string());
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > PGN_LINE_LENGTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push_str("\n\n");
+    out
+}
+
+// Plays the first engine against the second like selfplay does, swapping colours after every
+// game and moving to the next opening every two games. report gets called after every game
+pub fn run_match<F>(
+    first: &EngineSpec,
+    second: &EngineSpec,
+    config: &RefereeConfig,
+    mut report: F,
+) -> Result<MatchStats, String>
+where
+    F: FnMut(u32, Color, &GameRecord, &MatchStats),
+{
+    let mut engines = (EngineProcess::start(first)?, EngineProcess::start(second)?);
+    let mut stats = MatchStats::default();
+    for i in 0..config.games {
+        let opening = OPENINGS[(i as usize / 2) % OPENINGS.len()];
+        let (colour, record) = if i % 2 == 0 {
+            let record = play_game(&mut engines.0, &mut engines.1, opening, config)?;
+            (White, record)
+        } else {
+            let record = play_game(&mut engines.1, &mut engines.0, opening, config)?;
+            (Black, record)
+        };
+        if let Some(ref path) = config.pgn {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| {
+                    file.write_all(pgn(&record, i + 1, &config.time_control).as_bytes())
+                })
+                .map_err(|e| format!("Couldn't write {}, {}", path, e))?;
+        }
+        match record.result.winner {
+            None => stats.draws += 1,
+            Some(winner) if winner == colour => stats.wins += 1,
+            Some(_) => stats.losses += 1,
+        }
+        report(i + 1, colour, &record, &stats);
+    }
+    Ok(stats)
+}
+
+// Reads "[games=N] [maxplies=N] [tc=base+inc] [pgn=<file>] <engine> vs <engine>", where each
+// engine is uci:<command> or xboard:<command> followed by the arguments of the command
+pub fn parse_referee(args: &str) -> Result<(EngineSpec, EngineSpec, RefereeConfig), String> {
+    let usage = "Expected <engine> vs <engine>";
+    let mut engines: Vec<EngineSpec> = Vec::new();
+    let mut config = RefereeConfig::new(2 * OPENINGS.len() as u32);
+    // Whether the tokens are still arguments of the last engine
+    let mut open = false;
+    for token in args.split_whitespace() {
+        if token == "vs" {
+            if engines.len() != 1 || !open {
+                return Err(usage.to_string());
+            }
+            open = false;
+        } else if open {
+            if let Some(engine) = engines.last_mut() {
+                engine.args.push(token.to_string());
+            }
+        } else if token.starts_with("uci:") || token.starts_with("xboard:") {
+            engines.push(EngineSpec::parse(token)?);
+            open = true;
+        } else if !engines.is_empty() {
+            return Err(usage.to_string());
+        } else {
+            let (name, value) = match token.find('=') {
+                Some(i) => (&token[..i], &token[i + 1..]),
+                None => return Err(format!("Expected name=value, got {}", token)),
+            };
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("Bad value for {}: {}", name, value))
+            };
+            match name {
+                "games" => config.games = number()?,
+                "maxplies" => config.max_plies = number()?,
+                "tc" => config.time_control = TimeControl::parse(value)?,
+                "pgn" => config.pgn = Some(value.to_string()),
+                _ => return Err(format!("Unknown setting {}", name)),
+            }
+        }
+    }
+    if engines.len() != 2 {
+        return Err(usage.to_string());
+    }
+    let second = engines.pop().unwrap();
+    let first = engines.pop().unwrap();
+    Ok((first, second, config))
+}
+
+#[test]
+fn test_parse_referee() {
+    let (first, second, config) = parse_referee(
+        "games=4 tc=60+0.5 pgn=out.pgn uci:./stockfish vs xboard:/usr/bin/crafty --quiet",
+    )
+    .unwrap();
+    assert_eq!(first.protocol, Protocol::Uci);
+    assert_eq!(first.command, "./stockfish");
+    assert!(first.args.is_empty());
+    assert_eq!(second.protocol, Protocol::Xboard);
+    assert_eq!(second.args, vec!["--quiet".to_string()]);
+    assert_eq!(config.games, 4);
+    assert_eq!(config.max_plies, DEFAULT_MAX_PLIES);
+    assert_eq!(config.time_control.increment, Duration::from_millis(500));
+    assert_eq!(config.time_control.to_string(), "60+0.5");
+    assert_eq!(config.pgn, Some("out.pgn".to_string()));
+
+    assert!(parse_referee("uci:a").is_err());
+    assert!(parse_referee("uci:a vs").is_err());
+    assert!(parse_referee("uci:a vs b").is_err());
+    assert!(parse_referee("uci:a vs uci:b vs uci:c").is_err());
+    assert!(parse_referee("cecp:a vs uci:b").is_err());
+    assert!(parse_referee("tc=0 uci:a vs uci:b").is_err());
+    assert!(parse_referee("speed=fast uci:a vs uci:b").is_err());
+    assert_eq!(TimeControl::parse("5").unwrap().to_string(), "5");
+}
+
+#[test]
+fn test_pgn() {
+    let mut game = Game::new();
+    let mut moves = Vec::new();
+    for text in ["f2f3", "e7e5", "g2g4", "d8h4"].iter() {
+        let mov = game.parse_move(text).unwrap();
+        game.make_move(&mov).unwrap();
+        moves.push(mov);
+    }
+    let record = GameRecord {
+        white: "Lurri".to_string(),
+        black: "Other \"engine\"".to_string(),
+        moves,
+        result: GameResult {
+            winner: Some(Black),
+            termination: Termination::Checkmate,
+            plies: 4,
+        },
+    };
+    let time_control = TimeControl::parse("10+0.1").unwrap();
+    let text = pgn(&record, 3, &time_control);
+    assert!(text.starts_with("[Event \"Lurri referee match\"]\n"));
+    assert!(text.contains("[Round \"3\"]\n"));
+    assert!(text.contains("[Black \"Other 'engine'\"]\n"));
+    assert!(text.contains("[Result \"0-1\"]\n"));
+    assert!(text.contains("[TimeControl \"10+0.1\"]\n"));
+    assert!(text.ends_with("\n\n1. f3 e5 2. g4 Qh4# {Black wins by checkmate} 0-1\n\n"));
+
+    // Long games get wrapped
+    let record = GameRecord {
+        moves: ["g1f3", "g8f6", "f3g1", "f6g8"]
+            .iter()
+            .cycle()
+            .take(40)
+            .map(|text| Move::safe_from_string(text))
+            .collect(),
+        result: GameResult {
+            winner: None,
+            termination: Termination::Repetition,
+            plies: 40,
+        },
+        ..record
+    };
+    let text = pgn(&record, 1, &time_control);
+    assert!(text.lines().all(|line| line.len() <= PGN_LINE_LENGTH));
+    assert!(text.ends_with("20. Ng1 Ng8\n{Draw by threefold repetition} 1/2-1/2\n\n"));
+}
+
+#[test]
+fn test_adjudication() {
+    let game = Game::from_fen("7k/8/8/8/8/8/8/KR6 w - - 0 1").unwrap();
+    assert_eq!(
+        read_move(&game, "b1b8"),
+        Some(Move::safe_from_string("b1b8"))
+    );
+    assert_eq!(
+        read_move(&game, "Rb8+"),
+        Some(Move::safe_from_string("b1b8"))
+    );
+    assert_eq!(read_move(&game, "b1c2"), None);
+    assert_eq!(read_move(&game, "nonsense"), None);
+    assert_eq!(time_forfeit(&game, Black).winner, Some(White));
+    // Black only has the king, so white running out of time is a draw
+    assert_eq!(time_forfeit(&game, White).winner, None);
+}
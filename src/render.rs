@@ -0,0 +1,184 @@
+use game::Color::{Black, White};
+use game::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
+use game::*;
+use variant::Variant;
+
+// ANSI background colours, from the 256 colour palette
+const LIGHT_SQUARE: u8 = 180;
+const DARK_SQUARE: u8 = 137;
+const LAST_MOVE_SQUARE: u8 = 143;
+const CHECK_SQUARE: u8 = 167;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RenderOptions {
+    // The side shown at the bottom
+    pub orientation: Color,
+    pub unicode: bool,
+    pub colors: bool,
+    pub coordinates: bool,
+    // Marks the squares of the last move and the king in check
+    pub highlight: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            orientation: White,
+            unicode: false,
+            colors: false,
+            coordinates: true,
+            highlight: true,
+        }
+    }
+}
+
+pub fn glyph(piece: Option<Piece>, unicode: bool) -> char {
+    match (piece, unicode) {
+        (None, false) => '.',
+        (None, true) => '·',
+        (Some(piece), false) => {
+            let c = piece.tipo.to_string().chars().next().unwrap();
+            if piece.color == White {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        }
+        (Some(piece), true) => match piece {
+            piece!(White, King) => '♔',
+            piece!(White, Queen) => '♕',
+            piece!(White, Rook) => '♖',
+            piece!(White, Bishop) => '♗',
+            piece!(White, Knight) => '♘',
+            piece!(White, Pawn) => '♙',
+            piece!(Black, King) => '♚',
+            piece!(Black, Queen) => '♛',
+            piece!(Black, Rook) => '♜',
+            piece!(Black, Bishop) => '♝',
+            piece!(Black, Knight) => '♞',
+            piece!(Black, Pawn) => '♟',
+        },
+    }
+}
+
+impl Game {
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let mut xs: Vec<X> = (Position::ch2x('a')..=Position::ch2x('h')).collect();
+        let mut ys: Vec<Y> = (Position::ch2y('1')..=Position::ch2y('8')).rev().collect();
+        if options.orientation == Black {
+            xs.reverse();
+            ys.reverse();
+        }
+        let files: String = xs
+            .iter()
+            .map(|&x| format!(" {} ", Position::new(x, ys[0]).to_string().remove(0)))
+            .collect();
+        let last_move = match self.last_move {
+            Some(mov) if options.highlight => vec![mov.from, mov.to],
+            _ => Vec::new(),
+        };
+        let king = Piece::new(self.turn, King);
+        let check = if options.highlight && self.is_check() {
+            Position::all().find(|&pos| self.get_square(pos).contains(king))
+        } else {
+            None
+        };
+
+        let mut out = format!("{} Plays\n", self.turn);
+        if options.coordinates {
+            out.push_str(&format!("   {}\n", files));
+        }
+        for &y in ys.iter() {
+            let rank = Position::new(xs[0], y).to_string().remove(1);
+            if options.coordinates {
+                out.push_str(&format!("{}  ", rank));
+            }
+            for &x in xs.iter() {
+                let pos = Position::new(x, y);
+                let c = glyph(self.get_piece(pos), options.unicode);
+                let highlighted = if check == Some(pos) {
+                    Some(CHECK_SQUARE)
+                } else if last_move.contains(&pos) {
+                    Some(LAST_MOVE_SQUARE)
+                } else {
+                    None
+                };
+                if options.colors {
+                    let light = (x + y) % 2 == 1;
+                    let background =
+                        highlighted.unwrap_or(if light { LIGHT_SQUARE } else { DARK_SQUARE });
+                    out.push_str(&format!("\x1b[48;5;{}m {} \x1b[0m", background, c));
+                } else {
+                    // Without colours the marked squares are bracketed, () for check
+                    match highlighted {
+                        Some(CHECK_SQUARE) => out.push_str(&format!("({})", c)),
+                        Some(_) => out.push_str(&format!("[{}]", c)),
+                        None => out.push_str(&format!(" {} ", c)),
+                    }
+                }
+            }
+            if options.coordinates {
+                out.push_str(&format!("  {}", rank));
+            }
+            out.push('\n');
+        }
+        if options.coordinates {
+            out.push_str(&format!("   {}\n", files));
+        }
+        if self.variant == Variant::Crazyhouse {
+            for &color in [White, Black].iter() {
+                let pocket: Vec<String> = self.pockets[color.index()]
+                    .pieces()
+                    .iter()
+                    .map(|&tipo| glyph(Some(Piece::new(color, tipo)), options.unicode).to_string())
+                    .collect();
+                out.push_str(&format!("{} pocket: {}\n", color, pocket.join(" ")));
+            }
+        }
+        out
+    }
+}
+
+#[test]
+fn test_render() {
+    let mut game = Game::new();
+    let plain = RenderOptions::default();
+    let board = game.render(&plain);
+    let lines: Vec<&str> = board.lines().collect();
+    assert_eq!(lines[0], "White Plays");
+    assert_eq!(lines[1], "    a  b  c  d  e  f  g  h ");
+    assert_eq!(lines[2], "8   r  n  b  q  k  b  n  r   8");
+    assert_eq!(lines[9], "1   R  N  B  Q  K  B  N  R   1");
+
+    let flipped = game.render(&RenderOptions {
+        orientation: Black,
+        coordinates: false,
+        ..plain
+    });
+    let lines: Vec<&str> = flipped.lines().collect();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[1], " R  N  B  K  Q  B  N  R ");
+    assert_eq!(lines[8], " r  n  b  k  q  b  n  r ");
+
+    game.make_move(&Move::safe_from_string("e2e4")).unwrap();
+    let lines: Vec<String> = game.render(&plain).lines().map(String::from).collect();
+    assert_eq!(lines[6], "4   .  .  .  . [P] .  .  .   4");
+    assert_eq!(lines[8], "2   P  P  P  P [.] P  P  P   2");
+    let unicode = game.render(&RenderOptions {
+        unicode: true,
+        highlight: false,
+        ..plain
+    });
+    assert!(unicode.contains("♜  ♞  ♝  ♛  ♚  ♝  ♞  ♜"));
+    assert!(unicode.contains("·  ·  ·  ·  ♙  ·  ·  ·"));
+
+    let game = Game::from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+    assert!(game.render(&plain).contains("(k)"));
+    let colored = game.render(&RenderOptions {
+        colors: true,
+        ..plain
+    });
+    assert!(colored.contains(&format!("\x1b[48;5;{}m k ", CHECK_SQUARE)));
+    assert!(colored.contains(&format!("\x1b[48;5;{}m R ", DARK_SQUARE)));
+    assert!(colored.contains(&format!("\x1b[48;5;{}m . ", LIGHT_SQUARE)));
+}
@@ -0,0 +1,174 @@
+use game::PieceType::{King, Pawn};
+use game::*;
+
+fn file_char(pos: Position) -> char {
+    pos.to_string().chars().next().unwrap()
+}
+
+fn rank_char(pos: Position) -> char {
+    pos.to_string().chars().nth(1).unwrap()
+}
+
+fn piece_char(tipo: PieceType) -> char {
+    tipo.to_string()
+        .chars()
+        .next()
+        .unwrap()
+        .to_ascii_uppercase()
+}
+
+impl Game {
+    // Reads standard algebraic notation, like Nbd7, exd5, e8=Q+, O-O or the drop N@f3
+    pub fn parse_san(&self, san: &str) -> Result<Move, ParseError> {
+        let san = san.trim_end_matches(|c| "+#!?".contains(c));
+        let legal = self.legal_moves();
+        let matching: Vec<Move> = match san {
+            "O-O" | "0-0" => legal
+                .into_iter()
+                .filter(|mov| mov.tipo == MoveType::ShortCastling)
+                .collect(),
+            "O-O-O" | "0-0-0" => legal
+                .into_iter()
+                .filter(|mov| mov.tipo == MoveType::LongCastling)
+                .collect(),
+            // Drops are written the same way in every notation
+            _ if san.contains('@') => {
+                let drop = Move::from_string(san)?;
+                legal.into_iter().filter(|&mov| mov == drop).collect()
+            }
+            _ => {
+                let mut chars: Vec<char> = san.chars().filter(|&c| c != 'x' && c != '=').collect();
+                let promotion = match chars.last() {
+                    Some(&c) if "QRBN".contains(c) => {
+                        chars.pop();
+                        Some(PieceType::from_char(c.to_ascii_lowercase())?)
+                    }
+                    _ => None,
+                };
+                if chars.len() < 2 {
+                    return Err(ParseError::MalformedMove);
+                }
+                let to = Position::from_chars(chars[chars.len() - 2], chars[chars.len() - 1])?;
+                chars.truncate(chars.len() - 2);
+                let tipo = match chars.first() {
+                    Some(&c) if c.is_ascii_uppercase() => {
+                        chars.remove(0);
+                        PieceType::from_char(c.to_ascii_lowercase())?
+                    }
+                    _ => Pawn,
+                };
+                let (mut file, mut rank) = (None, None);
+                for &c in chars.iter() {
+                    match c {
+                        'a'..='h' => file = Some(Position::ch2x(c)),
+                        '1'..='8' => rank = Some(Position::ch2y(c)),
+                        _ => return Err(ParseError::MalformedMove),
+                    }
+                }
+                legal
+                    .into_iter()
+                    .filter(|mov| {
+                        mov.to == to
+                            && mov.tipo != MoveType::ShortCastling
+                            && mov.tipo != MoveType::LongCastling
+                            && self.get_piece(mov.from).map(|piece| piece.tipo) == Some(tipo)
+                            && file.is_none_or(|x| mov.from.x == x)
+                            && rank.is_none_or(|y| mov.from.y == y)
+                            && match (mov.tipo, promotion) {
+                                (MoveType::Promotion(pt), Some(promoted)) => pt == promoted,
+                                (MoveType::Promotion(_), None) => false,
+                                (_, promoted) => promoted.is_none(),
+                            }
+                    })
+                    .collect()
+            }
+        };
+        match matching.len() {
+            0 => Err(ParseError::IllegalMove),
+            1 => Ok(matching[0]),
+            _ => Err(ParseError::AmbiguousMove),
+        }
+    }
+
+    // Writes a legal move in standard algebraic notation
+    pub fn format_san(&self, mov: &Move) -> String {
+        let mut san = String::new();
+        match (mov.tipo, self.get_piece(mov.from)) {
+            (MoveType::ShortCastling, _) => san.push_str("O-O"),
+            (MoveType::LongCastling, _) => san.push_str("O-O-O"),
+            (MoveType::Drop(_), _) => san.push_str(&mov.to_string()),
+            (_, Some(piece)) => {
+                if piece.tipo == Pawn {
+                    if self.is_capture(mov) {
+                        san.push(file_char(mov.from));
+                    }
+                } else {
+                    san.push(piece_char(piece.tipo));
+                    // Other pieces of the same kind that can go to the same square
+                    let rivals: Vec<Position> = self
+                        .legal_moves()
+                        .iter()
+                        .filter(|other| {
+                            other.to == mov.to
+                                && other.from != mov.from
+                                && piece.tipo != King
+                                && self.get_piece(other.from) == Some(piece)
+                        })
+                        .map(|other| other.from)
+                        .collect();
+                    if !rivals.is_empty() {
+                        if rivals.iter().all(|pos| pos.x != mov.from.x) {
+                            san.push(file_char(mov.from));
+                        } else if rivals.iter().all(|pos| pos.y != mov.from.y) {
+                            san.push(rank_char(mov.from));
+                        } else {
+                            san.push_str(&mov.from.to_string());
+                        }
+                    }
+                }
+                if self.is_capture(mov) {
+                    san.push('x');
+                }
+                san.push_str(&mov.to.to_string());
+                if let MoveType::Promotion(pt) = mov.tipo {
+                    san.push('=');
+                    san.push(piece_char(pt));
+                }
+            }
+            (_, None) => return mov.to_string(),
+        }
+        let mut after = self.clone();
+        if after.make_move(mov).is_ok() && after.is_check() {
+            san.push(if after.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        san
+    }
+}
+
+#[test]
+fn test_san() {
+    let game = Game::from_fen("r3k2r/1P6/8/3pP3/8/2N1N3/8/R3K2R w KQkq d6 0 1").unwrap();
+    let san = |s: &str| game.parse_san(s).map(|mov| game.format_san(&mov));
+    assert_eq!(san("O-O"), Ok("O-O".to_string()));
+    assert_eq!(san("0-0-0"), Ok("O-O-O".to_string()));
+    assert_eq!(san("exd6"), Ok("exd6".to_string()));
+    assert_eq!(san("Ned1"), Ok("Ned1".to_string()));
+    assert_eq!(san("Ncxd5"), Ok("Ncxd5".to_string()));
+    assert_eq!(san("Ng4"), Ok("Ng4".to_string()));
+    assert_eq!(san("bxa8=Q+"), Ok("bxa8=Q+".to_string()));
+    assert_eq!(san("b8N"), Ok("b8=N".to_string()));
+    assert_eq!(san("Rd1"), Ok("Rd1".to_string()));
+    assert_eq!(san("Nxd5"), Err(ParseError::AmbiguousMove));
+    assert_eq!(san("b8"), Err(ParseError::IllegalMove));
+    assert_eq!(san("Ke3"), Err(ParseError::IllegalMove));
+    assert_eq!(san("x"), Err(ParseError::MalformedMove));
+
+    let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+    let mate = game.parse_san("Ra8#").unwrap();
+    assert_eq!(mate, Move::safe_from_string("a1a8"));
+    assert_eq!(game.format_san(&mate), "Ra8#");
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use eval::{EvalParams, PARAM_NAMES};
+use game::Color::{Black, White};
+use game::PieceType::{Bishop, King, Knight};
+use game::*;
+use lurri;
+use variant::{Outcome, Variant};
+
+// Short lines from the main openings, every one gets played twice so each side has both colours
+pub const OPENINGS: [&str; 12] = [
+    "e2e4 e7e5 g1f3 b8c6 f1b5",
+    "e2e4 e7e5 g1f3 b8c6 f1c4",
+    "e2e4 c7c5 g1f3 d7d6 d2d4",
+    "e2e4 c7c5 b1c3 b8c6",
+    "e2e4 e7e6 d2d4 d7d5",
+    "e2e4 c7c6 d2d4 d7d5",
+    "d2d4 d7d5 c2c4 e7e6",
+    "d2d4 d7d5 c2c4 c7c6",
+    "d2d4 g8f6 c2c4 g7g6 b1c3",
+    "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4",
+    "c2c4 e7e5 b1c3",
+    "g1f3 d7d5 g2g3",
+];
+
+// Games that get this long without a result are adjudicated as draws
+pub const DEFAULT_MAX_PLIES: u32 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerConfig {
+    pub depth: u32,
+    pub features: lurri::SearchFeatures,
+    pub eval: EvalParams,
+}
+
+impl PlayerConfig {
+    pub fn new(depth: u32) -> PlayerConfig {
+        PlayerConfig {
+            depth,
+            features: lurri::SearchFeatures::all(),
+            eval: EvalParams::default(),
+        }
+    }
+
+    fn engine(&self) -> lurri::Engine {
+        let mut engine = lurri::Engine::new();
+        engine.set_features(self.features);
+        engine.set_eval_params(self.eval);
+        engine
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Goal,
+    FiftyMoves,
+    Repetition,
+    InsufficientMaterial,
+    MaxPlies,
+    // Only in games between engine processes
+    IllegalMove,
+    TimeForfeit,
+    Resignation,
+    Disconnection,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Termination::Checkmate => "checkmate",
+                Termination::Stalemate => "stalemate",
+                Termination::Goal => "variant goal",
+                Termination::FiftyMoves => "fifty move rule",
+                Termination::Repetition => "threefold repetition",
+                Termination::InsufficientMaterial => "insufficient material",
+                Termination::MaxPlies => "move limit",
+                Termination::IllegalMove => "illegal move",
+                Termination::TimeForfeit => "time forfeit",
+                Termination::Resignation => "resignation",
+                Termination::Disconnection => "disconnection",
+            }
+        )
+    }
+}
+
+// The winner is None for draws
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GameResult {
+    pub winner: Option<Color>,
+    pub termination: Termination,
+    pub plies: u32,
+}
+
+// Only the material configurations where no sequence of moves can mate: bare kings, a single
+// minor piece, or bishops that all live on squares of one colour
+pub fn insufficient_material(game: &Game) -> bool {
+    let mut minors = Vec::new();
+    for pos in Position::all() {
+        match game.get_piece(pos).map(|piece| piece.tipo) {
+            None | Some(King) => {}
+            Some(Knight) => minors.push((Knight, pos)),
+            Some(Bishop) => minors.push((Bishop, pos)),
+            Some(_) => return false,
+        }
+    }
+    let square_colour = |pos: &Position| (pos.x + pos.y) % 2;
+    match minors.len() {
+        0 | 1 => true,
+        _ => {
+            minors.iter().all(|&(tipo, _)| tipo == Bishop)
+                && minors
+                    .iter()
+                    .all(|(_, pos)| square_colour(pos) == square_colour(&minors[0].1))
+        }
+    }
+}
+
+// Checks whether the game is over, seen holds how many times every position has occurred
+pub fn termination(game: &Game, seen: &HashMap<u64, u32>) -> Option<GameResult> {
+    let over = |outcome, termination| {
+        Some(GameResult {
+            winner: match outcome {
+                Outcome::Win(color) => Some(color),
+                Outcome::Draw => None,
+            },
+            termination,
+            plies: 0,
+        })
+    };
+    let rules = game.rules();
+    if let Some(outcome) = rules.goal(game) {
+        return over(outcome, Termination::Goal);
+    }
+    if game.legal_moves().is_empty() {
+        let termination = if game.is_check() {
+            Termination::Checkmate
+        } else {
+            Termination::Stalemate
+        };
+        return over(rules.no_moves(game), termination);
+    }
+    if game.halfmove_clock >= 100 {
+        return over(Outcome::Draw, Termination::FiftyMoves);
+    }
+    if seen.get(&game.hash()).map_or(0, |&n| n) >= 3 {
+        return over(Outcome::Draw, Termination::Repetition);
+    }
+    if game.variant == Variant::Standard && insufficient_material(game) {
+        return over(Outcome::Draw, Termination::InsufficientMaterial);
+    }
+    None
+}
+
+// Plays one game from the opening, white and black are the engines for each side
+pub fn play_game(
+    opening: &str,
+    white: (&lurri::Engine, u32),
+    black: (&lurri::Engine, u32),
+    max_plies: u32,
+) -> Result<GameResult, String> {
+    let mut game = Game::new();
+    for token in opening.split_whitespace() {
+        let mov = game
+            .parse_move(token)
+            .map_err(|e| format!("{} ({})", e, token))?;
+        game.make_move(&mov)
+            .map_err(|e| format!("{} ({})", e, token))?;
+    }
+    let mut seen = HashMap::new();
+    let mut plies = 0;
+    loop {
+        *seen.entry(game.hash()).or_insert(0) += 1;
+        if let Some(result) = termination(&game, &seen) {
+            return Ok(GameResult { plies, ..result });
+        }
+        if plies >= max_plies {
+            return Ok(GameResult {
+                winner: None,
+                termination: Termination::MaxPlies,
+                plies,
+            });
+        }
+        let (engine, depth) = match game.turn {
+            White => white,
+            Black => black,
+        };
+        let mov = engine
+            .get_move(&game, depth)
+            .best_move
+            .ok_or_else(|| format!("No move found in {}", game.to_fen()))?;
+        game.make_move(&mov)
+            .map_err(|e| format!("{} ({} in {})", e, mov, game.to_fen()))?;
+        plies += 1;
+    }
+}
+
+// Results from the point of view of the first player
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// Scores of 0% and 100% get a large but finite difference
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+impl MatchStats {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games())
+    }
+
+    // Variance of the score of a single game
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        (f64::from(self.wins) * (1.0 - score).powi(2)
+            + f64::from(self.draws) * (0.5 - score).powi(2)
+            + f64::from(self.losses) * score.powi(2))
+            / f64::from(self.games())
+    }
+
+    pub fn elo(&self) -> f64 {
+        elo_from_score(self.score())
+    }
+
+    // Half the width of the 95% confidence interval of the Elo difference
+    pub fn elo_error(&self) -> f64 {
+        let deviation = (self.variance() / f64::from(self.games())).sqrt();
+        let low = self.score() - 1.96 * deviation;
+        let high = self.score() + 1.96 * deviation;
+        (elo_from_score(high) - elo_from_score(low)) / 2.0
+    }
+
+    // Log likelihood ratio of the Elo difference being elo1 rather than elo0, using the normal
+    // approximation of the game scores
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let variance = self.variance();
+        if self.games() == 0 || variance == 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (score_from_elo(elo0), score_from_elo(elo1));
+        f64::from(self.games()) * (score1 - score0) * (2.0 * self.score() - score0 - score1)
+            / (2.0 * variance)
+    }
+}
+
+impl fmt::Display for MatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        if self.games() > 0 {
+            write!(
+                f,
+                ", score {:.1}%, Elo {:+.1} +/- {:.1}",
+                self.score() * 100.0,
+                self.elo(),
+                self.elo_error()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Sequential probability ratio test of H0: Elo difference is elo0 against H1: it is elo1
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Sprt {
+        Sprt {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Some(true) once H1 is accepted, Some(false) once H0 is
+    pub fn decision(&self, stats: &MatchStats) -> Option<bool> {
+        let llr = stats.llr(self.elo0, self.elo1);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(true)
+        } else if llr <= lower {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MatchConfig {
+    pub games: u32,
+    pub max_plies: u32,
+    pub sprt: Option<Sprt>,
+}
+
+impl MatchConfig {
+    pub fn new(games: u32) -> MatchConfig {
+        MatchConfig {
+            games,
+            max_plies: DEFAULT_MAX_PLIES,
+            sprt: None,
+        }
+    }
+}
+
+// Plays the first player against the second, swapping colours after every game and moving to
+// the next opening every two games. report gets called after every game with its number, the
+// colour the first player had and the result
+pub fn play_match<F>(
+    first: &PlayerConfig,
+    second: &PlayerConfig,
+    config: &MatchConfig,
+    mut report: F,
+) -> Result<MatchStats, String>
+where
+    F: FnMut(u32, Color, &GameResult, &MatchStats),
+{
+    let engines = (first.engine(), second.engine());
+    let mut stats = MatchStats::default();
+    for i in 0..config.games {
+        let opening = OPENINGS[(i as usize / 2) % OPENINGS.len()];
+        engines.0.clear();
+        engines.1.clear();
+        let first_player = (&engines.0, first.depth);
+        let second_player = (&engines.1, second.depth);
+        let (colour, result) = if i % 2 == 0 {
+            let result = play_game(opening, first_player, second_player, config.max_plies)?;
+            (White, result)
+        } else {
+            let result = play_game(opening, second_player, first_player, config.max_plies)?;
+            (Black, result)
+        };
+        match result.winner {
+            None => stats.draws += 1,
+            Some(winner) if winner == colour => stats.wins += 1,
+            Some(_) => stats.losses += 1,
+        }
+        report(i + 1, colour, &result, &stats);
+        if config.sprt.and_then(|sprt| sprt.decision(&stats)).is_some() {
+            break;
+        }
+    }
+    Ok(stats)
+}
+
+// Reads "[games=N] [maxplies=N] [sprt=elo0,elo1] <player> vs <player>", where each player is
+// a list of depth=N, evalfile=<path>, <parameter>=N and <feature>=on|off settings. Unset
+// values come from the default player searching to the given depth
+pub fn parse_match(
+    args: &str,
+    depth: u32,
+) -> Result<(PlayerConfig, PlayerConfig, MatchConfig), String> {
+    let mut players = [PlayerConfig::new(depth), PlayerConfig::new(depth)];
+    let mut config = MatchConfig::new(2 * OPENINGS.len() as u32);
+    let mut current = 0;
+    for token in args.split_whitespace() {
+        if token == "vs" {
+            current += 1;
+            if current > 1 {
+                return Err("Only two players can play".to_string());
+            }
+            continue;
+        }
+        let (name, value) = match token.find('=') {
+            Some(i) => (&token[..i], &token[i + 1..]),
+            None => return Err(format!("Expected name=value, got {}", token)),
+        };
+        let number = || {
+            value
+                .parse::<i32>()
+                .map_err(|_| format!("Bad value for {}: {}", name, value))
+        };
+        let on = || match value {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(format!("Expected on or off for {}", name)),
+        };
+        let player = &mut players[current];
+        match name {
+            "games" => config.games = number()?.max(0) as u32,
+            "maxplies" => config.max_plies = number()?.max(0) as u32,
+            "sprt" => {
+                let elos: Vec<f64> = value.split(',').filter_map(|n| n.parse().ok()).collect();
+                if elos.len() != 2 {
+                    return Err(format!("Expected sprt=elo0,elo1, got {}", token));
+                }
+                config.sprt = Some(Sprt::new(elos[0], elos[1]));
+            }
+            "depth" => player.depth = number()?.max(1) as u32,
+            "evalfile" => player.eval = EvalParams::load(value)?,
+            _ if PARAM_NAMES.contains(&name) => player.eval.set(name, number()?)?,
+            "nullmove" => player.features.null_move = on()?,
+            "lmr" => player.features.late_move_reductions = on()?,
+            "aspiration" => player.features.aspiration_windows = on()?,
+            "pvs" => player.features.principal_variation_search = on()?,
+            "extensions" => player.features.extensions = on()?,
+            "quiescence" => player.features.quiescence = on()?,
+            _ => return Err(format!("Unknown setting {}", name)),
+        }
+    }
+    Ok((players[0], players[1], config))
+}
+
+#[test]
+fn test_termination() {
+    let over = |fen| {
+        let game = Game::from_fen(fen).unwrap();
+        let mut seen = HashMap::new();
+        seen.insert(game.hash(), 1);
+        termination(&game, &seen).map(|result| (result.winner, result.termination))
+    };
+    assert_eq!(
+        over("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"),
+        Some((Some(White), Termination::Checkmate))
+    );
+    assert_eq!(
+        over("7k/8/6QK/8/8/8/8/8 b - - 0 1"),
+        Some((None, Termination::Stalemate))
+    );
+    assert_eq!(
+        over("7k/8/8/8/8/8/8/KR6 w - - 100 80"),
+        Some((None, Termination::FiftyMoves))
+    );
+    assert_eq!(
+        over("7k/8/8/8/8/8/8/KN6 w - - 0 1"),
+        Some((None, Termination::InsufficientMaterial))
+    );
+    assert_eq!(over("7k/8/8/8/8/8/1b6/KB6 w - - 0 1"), None);
+    assert_eq!(
+        over("7k/8/8/8/8/1b6/8/KB6 w - - 0 1"),
+        Some((None, Termination::InsufficientMaterial))
+    );
+    assert_eq!(over("7k/8/8/8/8/8/P7/K7 w - - 0 1"), None);
+
+    let game = Game::from_fen("7k/8/8/8/8/8/8/KR6 w - - 0 1").unwrap();
+    let mut seen = HashMap::new();
+    seen.insert(game.hash(), 3);
+    assert_eq!(
+        termination(&game, &seen).unwrap().termination,
+        Termination::Repetition
+    );
+}
+
+#[test]
+fn test_match_stats() {
+    let stats = MatchStats {
+        wins: 30,
+        draws: 40,
+        losses: 30,
+    };
+    assert_eq!(stats.score(), 0.5);
+    assert!(stats.elo().abs() < 1e-9);
+    assert!(stats.elo_error() > 40.0 && stats.elo_error() < 60.0);
+    assert!(stats.llr(0.0, 10.0) < 0.0);
+
+    let stats = MatchStats {
+        wins: 75,
+        draws: 0,
+        losses: 25,
+    };
+    assert!((stats.elo() - 190.8).abs() < 0.1);
+    assert_eq!(Sprt::new(0.0, 50.0).decision(&stats), Some(true));
+    assert_eq!(Sprt::new(200.0, 300.0).decision(&stats), None);
+    assert_eq!(Sprt::new(300.0, 400.0).decision(&stats), Some(false));
+
+    let stats = MatchStats {
+        wins: 2,
+        draws: 0,
+        losses: 0,
+    };
+    assert!(stats.elo().is_finite() && stats.elo() > 1000.0);
+    assert!(stats.elo_error().is_finite());
+    assert!(!stats.to_string().contains("inf"));
+    let swapped = MatchStats {
+        wins: 0,
+        losses: 2,
+        ..stats
+    };
+    assert!((swapped.elo() + stats.elo()).abs() < 1.0);
+}
+
+#[test]
+fn test_parse_match() {
+    let (first, second, config) =
+        parse_match("games=10 sprt=0,5 depth=3 mobility=2 vs nullmove=off", 4).unwrap();
+    assert_eq!(config.games, 10);
+    assert_eq!(config.sprt, Some(Sprt::new(0.0, 5.0)));
+    assert_eq!(first.depth, 3);
+    assert_eq!(first.eval.mobility, 2);
+    assert!(first.features.null_move);
+    assert_eq!(second.depth, 4);
+    assert!(!second.features.null_move);
+    assert!(parse_match("depth=3 vs depth=2 vs depth=1", 4).is_err());
+    assert!(parse_match("speed=fast", 4).is_err());
+    assert!(parse_match("sprt=5", 4).is_err());
+}
+
+#[test]
+fn test_play_match() {
+    let mut config = MatchConfig::new(2);
+    config.max_plies = 20;
+    let mut colours = Vec::new();
+    let stats = play_match(
+        &PlayerConfig::new(2),
+        &PlayerConfig::new(1),
+        &config,
+        |_, colour, result, _| {
+            assert!(result.plies <= 20);
+            colours.push(colour);
+        },
+    )
+    .unwrap();
+    assert_eq!(stats.games(), 2);
+    assert_eq!(colours, vec![White, Black]);
+}
@@ -0,0 +1,184 @@
+use std::io;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use shakmaty::{Bitboard, Board, CastlingMode, Chess, FromSetup, Role, Setup, Square};
+use shakmaty_syzygy::Dtz;
+use shakmaty_syzygy::Tablebase as Tables;
+pub use shakmaty_syzygy::Wdl;
+
+use game::*;
+use variant::Variant;
+
+// Syzygy tables are probed through shakmaty, positions get there through their FEN
+pub struct Tablebase {
+    tables: Tables<Chess>,
+}
+
+impl Tablebase {
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Tablebase> {
+        let mut tables = Tables::new();
+        tables.add_directory(dir)?;
+        Ok(Tablebase { tables })
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    // Counting the pieces is much cheaper than building the position, so it goes first
+    fn position(&self, game: &Game) -> Option<Chess> {
+        // The tables only hold standard chess
+        if game.castling.any() || game.variant != Variant::Standard {
+            return None;
+        }
+        let pieces = Position::all()
+            .filter(|&pos| game.get_piece(pos).is_some())
+            .count();
+        if pieces > self.tables.max_pieces() {
+            return None;
+        }
+        chess_position(game)
+    }
+
+    // Outcome for the side to move, only available right after a capture or a pawn move
+    pub fn probe_wdl(&self, game: &Game) -> Option<Wdl> {
+        if game.halfmove_clock != 0 {
+            return None;
+        }
+        self.tables
+            .probe_wdl_after_zeroing(&self.position(game)?)
+            .ok()
+    }
+
+    // The move that keeps the best outcome while zeroing the 50 move counter the soonest,
+    // along with the outcome for the side to move under the 50 move rule
+    pub fn best_move(&self, game: &Game) -> Option<(Move, Wdl)> {
+        let position = self.position(game)?;
+        let wdl = self.tables.probe_wdl_after_zeroing(&position).ok()?;
+        let (mov, dtz) = self.tables.best_move(&position).ok()??;
+        let wdl = root_wdl(
+            wdl,
+            mov.is_zeroing(),
+            dtz.ignore_rounding(),
+            game.halfmove_clock,
+        );
+        Some((from_chess_move(game, mov)?, wdl))
+    }
+}
+
+// The outcome of the root after the moves already played since the last zeroing. The DTZ is
+// the one of the position after the best move, for the opponent. A rounded DTZ can be one
+// ply too long, so a win right at the limit may be reported as a cursed one
+fn root_wdl(wdl: Wdl, zeroing: bool, dtz_after: Dtz, halfmove_clock: u32) -> Wdl {
+    if halfmove_clock == 0 || wdl == Wdl::Draw {
+        return wdl;
+    }
+    let dtz = match wdl {
+        _ if !zeroing => -dtz_after.add_plies(1),
+        Wdl::Win | Wdl::CursedWin => Dtz(1),
+        _ => Dtz(-1),
+    };
+    Wdl::from_dtz(dtz.add_plies(halfmove_clock))
+}
+
+fn square(pos: Position) -> Square {
+    Square::new(pos.index() as u32)
+}
+
+// Built square by square, probes happen inside the search
+fn chess_position(game: &Game) -> Option<Chess> {
+    let mut board = Board::empty();
+    for pos in Position::all() {
+        if let Some(piece) = game.get_piece(pos) {
+            let role = match piece.tipo {
+                PieceType::Pawn => Role::Pawn,
+                PieceType::Knight => Role::Knight,
+                PieceType::Bishop => Role::Bishop,
+                PieceType::Rook => Role::Rook,
+                PieceType::Queen => Role::Queen,
+                PieceType::King => Role::King,
+            };
+            board.set_piece_at(square(pos), role.of(chess_color(piece.color)));
+        }
+    }
+    let rights = game.castling;
+    let mut castling_rights = Bitboard::EMPTY;
+    for &(allowed, file, color) in [
+        (rights.white_short, rights.short_rook_file, Color::White),
+        (rights.white_long, rights.long_rook_file, Color::White),
+        (rights.black_short, rights.short_rook_file, Color::Black),
+        (rights.black_long, rights.long_rook_file, Color::Black),
+    ]
+    .iter()
+    {
+        if allowed {
+            castling_rights.add(square(Position::new(file, Game::back_rank(color))));
+        }
+    }
+    let setup = Setup {
+        board,
+        turn: chess_color(game.turn),
+        castling_rights,
+        ep_square: game.en_passant.map(square),
+        halfmoves: game.halfmove_clock,
+        fullmoves: NonZeroU32::new(game.fullmove_number)?,
+        ..Setup::empty()
+    };
+    Chess::from_setup(setup, CastlingMode::Standard).ok()
+}
+
+fn chess_color(color: Color) -> shakmaty::Color {
+    match color {
+        Color::White => shakmaty::Color::White,
+        Color::Black => shakmaty::Color::Black,
+    }
+}
+
+fn from_chess_move(game: &Game, mov: shakmaty::Move) -> Option<Move> {
+    game.parse_move(&mov.to_uci(CastlingMode::Standard).to_string())
+        .ok()
+}
+
+#[test]
+fn test_missing_tables() {
+    assert!(Tablebase::open("/nonexistent/syzygy").is_err());
+
+    let tablebase = Tablebase {
+        tables: Tables::new(),
+    };
+    let game = Game::from_fen("7k/8/8/8/8/8/8/KQ6 w - - 0 1").unwrap();
+    assert!(tablebase.position(&game).is_none());
+    assert_eq!(tablebase.probe_wdl(&game), None);
+    assert_eq!(tablebase.best_move(&game), None);
+}
+
+#[test]
+fn test_chess_conversion() {
+    use shakmaty::Position as ChessPosition;
+
+    let game = Game::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+    let position = chess_position(&game).unwrap();
+    let moves: Vec<Move> = position
+        .legal_moves()
+        .into_iter()
+        .map(|mov| from_chess_move(&game, mov).unwrap())
+        .collect();
+    assert_eq!(moves.len(), 28);
+    assert!(moves.contains(&game.parse_move("e1g1").unwrap()));
+    assert!(moves.contains(&game.parse_move("e1c1").unwrap()));
+    assert!(moves.contains(&game.parse_move("e5d6").unwrap()));
+}
+
+#[test]
+fn test_root_wdl() {
+    // Mate in the tables but 60 plies away from the next capture, with 30 already played
+    assert_eq!(root_wdl(Wdl::Win, false, Dtz(-59), 0), Wdl::Win);
+    assert_eq!(root_wdl(Wdl::Win, false, Dtz(-59), 30), Wdl::Win);
+    assert_eq!(root_wdl(Wdl::Win, false, Dtz(-59), 41), Wdl::CursedWin);
+    assert_eq!(root_wdl(Wdl::Loss, false, Dtz(59), 41), Wdl::BlessedLoss);
+    assert_eq!(root_wdl(Wdl::Loss, false, Dtz(59), 40), Wdl::Loss);
+    // Zeroing right away always keeps the win
+    assert_eq!(root_wdl(Wdl::Win, true, Dtz(-80), 99), Wdl::Win);
+    assert_eq!(root_wdl(Wdl::Draw, false, Dtz(0), 50), Wdl::Draw);
+}
@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use game::*;
+
+// Sudden death games are planned as if this many moves were left
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// Kept back from every move for the protocols and the process switches
+const MOVE_OVERHEAD: Duration = Duration::from_millis(20);
+// The hard limit is this many times the soft one, but never more than the share of the clock
+// below
+const HARD_RATIO: u32 = 4;
+const MAX_CLOCK_SHARE: f64 = 0.75;
+// How much the soft limit grows when the best move changes or the score drops, and how far
+// it can go
+const BEST_MOVE_CHANGE_EXTENSION: f64 = 0.5;
+const SCORE_DROP_EXTENSION: f64 = 0.5;
+const MAX_EXTENSION: f64 = 2.5;
+// In the units of the evaluation, where a pawn is worth 10
+const SCORE_DROP: i32 = 3;
+// The extension fades by this factor after every iteration that changes nothing
+const EXTENSION_DECAY: f64 = 0.8;
+
+// The clock of the side to move as the protocols report it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    // Moves until the next time control, None when all the game is in the remaining time
+    pub moves_to_go: Option<u32>,
+}
+
+impl Clock {
+    // No new iteration starts after the soft limit, the search gets aborted at the hard one
+    pub fn limits(&self) -> (Duration, Duration) {
+        let available = self.remaining.saturating_sub(MOVE_OVERHEAD);
+        let moves = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let hard = available.mul_f64(MAX_CLOCK_SHARE);
+        let soft = (available / moves + self.increment * 3 / 4).min(hard);
+        (soft, (soft * HARD_RATIO).min(hard))
+    }
+}
+
+// Decides after every iteration whether the search should go one deeper
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeManager {
+    soft: Duration,
+    hard: Duration,
+    // Grows the soft limit while the search is unstable
+    extension: f64,
+    previous: Option<(Move, i32)>,
+    // With a single legal move there is nothing to think about
+    forced: bool,
+}
+
+impl TimeManager {
+    pub fn new(clock: &Clock, game: &Game) -> TimeManager {
+        let (soft, hard) = clock.limits();
+        TimeManager {
+            soft,
+            hard,
+            extension: 1.0,
+            previous: None,
+            forced: game.legal_moves().len() == 1,
+        }
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft.mul_f64(self.extension).min(self.hard)
+    }
+
+    // Takes the best move and score of the iteration that just finished, the score relative
+    // to the side to move
+    pub fn keep_searching(
+        &mut self,
+        best_move: Option<Move>,
+        score: i32,
+        elapsed: Duration,
+    ) -> bool {
+        if self.forced {
+            return false;
+        }
+        let mut extension = self.extension;
+        if let (Some((previous_move, previous_score)), Some(mov)) = (self.previous, best_move) {
+            if mov != previous_move {
+                extension += BEST_MOVE_CHANGE_EXTENSION;
+            }
+            if score <= previous_score - SCORE_DROP {
+                extension += SCORE_DROP_EXTENSION;
+            }
+        }
+        self.extension = if extension > self.extension {
+            extension.min(MAX_EXTENSION)
+        } else {
+            (extension * EXTENSION_DECAY).max(1.0)
+        };
+        self.previous = best_move.map(|mov| (mov, score));
+        elapsed < self.soft_limit()
+    }
+}
+
+// Reads the arguments of xboard's level command: moves per session, base time in minutes or
+// minutes:seconds, and increment in seconds
+pub fn parse_level(args: &str) -> Option<(u32, Duration, Duration)> {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    if args.len() != 3 {
+        return None;
+    }
+    let moves = args[0].parse().ok()?;
+    let base = match args[1].find(':') {
+        Some(i) => {
+            let minutes: u64 = args[1][..i].parse().ok()?;
+            let seconds: u64 = args[1][i + 1..].parse().ok()?;
+            Duration::from_secs(minutes * 60 + seconds)
+        }
+        None => Duration::from_secs(args[1].parse::<u64>().ok()? * 60),
+    };
+    let increment = args[2].parse::<f64>().ok().filter(|&n| n >= 0.0)?;
+    Some((moves, base, Duration::from_secs_f64(increment)))
+}
+
+// Moves left until the next session of a level with the given moves per session, 0 meaning
+// the whole game is one session
+pub fn moves_to_go(moves_per_session: u32, game: &Game) -> Option<u32> {
+    if moves_per_session == 0 {
+        return None;
+    }
+    let played = game.fullmove_number.saturating_sub(1);
+    Some(moves_per_session - played % moves_per_session)
+}
+
+#[test]
+fn test_clock_limits() {
+    let minute = Clock {
+        remaining: Duration::from_secs(60),
+        increment: Duration::from_secs(0),
+        moves_to_go: None,
+    };
+    let (soft, hard) = minute.limits();
+    assert_eq!(soft, Duration::from_millis(59_980) / 30);
+    assert_eq!(hard, soft * 4);
+
+    let increment = Clock {
+        increment: Duration::from_secs(1),
+        ..minute
+    };
+    assert_eq!(increment.limits().0, soft + Duration::from_millis(750));
+
+    // The last move before the time control can use most of the clock, but not all of it
+    let last_move = Clock {
+        moves_to_go: Some(1),
+        ..minute
+    };
+    let (soft, hard) = last_move.limits();
+    assert_eq!(soft, hard);
+    assert!(hard < Duration::from_secs(46));
+
+    let almost_out = Clock {
+        remaining: Duration::from_millis(5),
+        ..minute
+    };
+    let (soft, hard) = almost_out.limits();
+    assert_eq!(
+        (soft, hard),
+        (Duration::from_secs(0), Duration::from_secs(0))
+    );
+}
+
+#[test]
+fn test_time_manager() {
+    let clock = Clock {
+        remaining: Duration::from_secs(32),
+        increment: Duration::from_secs(0),
+        moves_to_go: None,
+    };
+    let game = Game::new();
+    let mut manager = TimeManager::new(&clock, &game);
+    let soft = manager.soft_limit();
+    assert!(soft > Duration::from_millis(1000) && soft < Duration::from_millis(1100));
+    let (e4, d4) = (
+        Move::safe_from_string("e2e4"),
+        Move::safe_from_string("d2d4"),
+    );
+    assert!(manager.keep_searching(Some(e4), 5, Duration::from_millis(10)));
+    assert!(manager.keep_searching(Some(e4), 5, Duration::from_millis(500)));
+    assert!(!manager.keep_searching(Some(e4), 5, soft));
+
+    // A new best move and a falling score each give the search more time
+    assert!(manager.keep_searching(Some(d4), 5, soft));
+    assert!(manager.soft_limit() > soft);
+    let extended = manager.soft_limit();
+    assert!(manager.keep_searching(Some(d4), 0, extended - Duration::from_millis(1)));
+    assert!(manager.soft_limit() > extended);
+    // Which fades once the search settles
+    let longest = manager.soft_limit();
+    manager.keep_searching(Some(d4), 0, Duration::from_millis(10));
+    assert!(manager.soft_limit() < longest);
+    for _ in 0..20 {
+        manager.keep_searching(Some(d4), 0, Duration::from_millis(10));
+    }
+    assert_eq!(manager.soft_limit(), soft);
+    assert!(manager.soft_limit() <= manager.hard_limit());
+
+    let game = Game::from_fen("7k/8/8/8/8/8/6q1/7K w - - 0 1").unwrap();
+    let mut manager = TimeManager::new(&clock, &game);
+    assert!(!manager.keep_searching(None, 0, Duration::from_millis(0)));
+}
+
+#[test]
+fn test_parse_level() {
+    assert_eq!(
+        parse_level("40 5 0"),
+        Some((40, Duration::from_secs(300), Duration::from_secs(0)))
+    );
+    assert_eq!(
+        parse_level("0 2:30 1.5"),
+        Some((0, Duration::from_secs(150), Duration::from_millis(1500)))
+    );
+    assert_eq!(parse_level("0 2:xx 1"), None);
+    assert_eq!(parse_level("40 5"), None);
+
+    let mut game = Game::new();
+    assert_eq!(moves_to_go(0, &game), None);
+    assert_eq!(moves_to_go(40, &game), Some(40));
+    game.fullmove_number = 41;
+    assert_eq!(moves_to_go(40, &game), Some(40));
+    game.fullmove_number = 45;
+    assert_eq!(moves_to_go(40, &game), Some(36));
+}
@@ -0,0 +1,183 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use game::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
+use game::*;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TtEntry {
+    pub mov: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+// Every slot keeps the key xored with the data, so a slot torn by two threads writing at the
+// same time reads as a miss instead of as a wrong entry. That makes locks unnecessary.
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+const SLOT_BYTES: usize = 16;
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let count = (megabytes.max(1) << 20) / SLOT_BYTES;
+        TranspositionTable {
+            slots: (0..count)
+                .map(|_| Slot {
+                    key: AtomicU64::new(0),
+                    data: AtomicU64::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    pub fn get(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        if data == 0 || slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Some(unpack(data))
+    }
+
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let data = pack(entry);
+        let slot = self.slot(key);
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// Data layout: score in bits 0-31, depth in 32-39, bound in 40-41 and the move in 42-57
+fn pack(entry: TtEntry) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    u64::from(entry.score as u32)
+        | u64::from(entry.depth.min(255)) << 32
+        | bound << 40
+        | u64::from(entry.mov.map_or(0, encode_move)) << 42
+}
+
+fn unpack(data: u64) -> TtEntry {
+    TtEntry {
+        score: data as u32 as i32,
+        depth: ((data >> 32) & 0xFF) as u32,
+        bound: match (data >> 40) & 0b11 {
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => Bound::Exact,
+        },
+        mov: decode_move(((data >> 42) & 0xFFFF) as u16),
+    }
+}
+
+fn square(pos: Position) -> u16 {
+    if (2..10).contains(&pos.x) && (2..10).contains(&pos.y) {
+        pos.index() as u16
+    } else {
+        0
+    }
+}
+
+// 6 bits for each square and 4 for the move type, 0 is never a real move
+fn encode_move(mov: Move) -> u16 {
+    let tipo = match mov.tipo {
+        MoveType::Normal => 0,
+        MoveType::ShortCastling => 1,
+        MoveType::LongCastling => 2,
+        MoveType::Promotion(Queen) => 3,
+        MoveType::Promotion(Rook) => 4,
+        MoveType::Promotion(Bishop) => 5,
+        MoveType::Promotion(Knight) => 6,
+        MoveType::Promotion(King) => 7,
+        MoveType::Promotion(Pawn) => 8,
+        MoveType::Drop(Queen) => 9,
+        MoveType::Drop(Rook) => 10,
+        MoveType::Drop(Bishop) => 11,
+        MoveType::Drop(Knight) => 12,
+        MoveType::Drop(Pawn) => 13,
+        MoveType::Drop(King) => 14,
+    };
+    square(mov.from) | square(mov.to) << 6 | tipo << 12
+}
+
+fn decode_move(code: u16) -> Option<Move> {
+    if code == 0 {
+        return None;
+    }
+    let tipo = match code >> 12 {
+        1 => MoveType::ShortCastling,
+        2 => MoveType::LongCastling,
+        3 => MoveType::Promotion(Queen),
+        4 => MoveType::Promotion(Rook),
+        5 => MoveType::Promotion(Bishop),
+        6 => MoveType::Promotion(Knight),
+        7 => MoveType::Promotion(King),
+        8 => MoveType::Promotion(Pawn),
+        9 => MoveType::Drop(Queen),
+        10 => MoveType::Drop(Rook),
+        11 => MoveType::Drop(Bishop),
+        12 => MoveType::Drop(Knight),
+        13 => MoveType::Drop(Pawn),
+        14 => MoveType::Drop(King),
+        _ => MoveType::Normal,
+    };
+    Some(Move::new(
+        Position::from_index((code & 0x3F) as usize),
+        Position::from_index(((code >> 6) & 0x3F) as usize),
+        tipo,
+    ))
+}
+
+#[test]
+fn test_tt_store() {
+    let tt = TranspositionTable::new(1);
+    let entry = TtEntry {
+        mov: Some(Move::safe_from_string("e7e8n")),
+        score: -1234,
+        depth: 7,
+        bound: Bound::Upper,
+    };
+    assert_eq!(tt.get(42), None);
+    tt.store(42, entry);
+    assert_eq!(tt.get(42), Some(entry));
+    assert_eq!(tt.get(43), None);
+
+    let entry = TtEntry {
+        mov: None,
+        score: 999_990,
+        depth: 3,
+        bound: Bound::Exact,
+    };
+    tt.store(42, entry);
+    assert_eq!(tt.get(42), Some(entry));
+    tt.clear();
+    assert_eq!(tt.get(42), None);
+}
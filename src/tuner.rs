@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::Path;
+
+use eval::{EvalParams, PARAM_COUNT};
+use game::*;
+
+// A labelled position, kept as its evaluation terms since the evaluation is linear in the
+// parameters. The result is the score white got in the game
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sample {
+    terms: [i32; PARAM_COUNT],
+    result: f64,
+}
+
+// "<fen> <result>" where the result is 1-0, 0-1, 1/2-1/2 or the score of white as a number,
+// optionally quoted or in brackets
+pub fn parse_sample(line: &str) -> Result<Sample, String> {
+    let line = line.trim();
+    let split = line
+        .rfind(char::is_whitespace)
+        .ok_or_else(|| format!("Expected a position and a result: {}", line))?;
+    let (fen, result) = line.split_at(split);
+    let result = match result.trim_matches(|c: char| c.is_whitespace() || "\"[];".contains(c)) {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        number => match number.parse::<f64>() {
+            Ok(score) if (0.0..=1.0).contains(&score) => score,
+            _ => return Err(format!("Bad result: {}", number)),
+        },
+    };
+    let game = Game::from_fen(fen).map_err(|e| e.to_string())?;
+    Ok(Sample {
+        terms: game.eval_terms(),
+        result,
+    })
+}
+
+// Blank lines and lines starting with # are skipped
+pub fn load_samples<P: AsRef<Path>>(path: P) -> Result<Vec<Sample>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    text.lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| parse_sample(line).map_err(|e| format!("Line {}: {}", i + 1, e)))
+        .collect()
+}
+
+// Expected score of white for an evaluation, scaled by k like centipawns are in the Elo formula
+fn sigmoid(eval: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * f64::from(eval * 10) / 400.0))
+}
+
+fn evaluate(sample: &Sample, values: &[i32; PARAM_COUNT]) -> i32 {
+    sample
+        .terms
+        .iter()
+        .zip(values.iter())
+        .map(|(t, v)| t * v)
+        .sum()
+}
+
+// Mean squared difference between the results and the scores predicted by the evaluation
+pub fn error(samples: &[Sample], params: &EvalParams, k: f64) -> f64 {
+    let values = params.values();
+    let total: f64 = samples
+        .iter()
+        .map(|sample| (sample.result - sigmoid(evaluate(sample, &values), k)).powi(2))
+        .sum();
+    total / samples.len().max(1) as f64
+}
+
+// The scaling constant that fits the current parameters best, found with a ternary search
+pub fn fit_k(samples: &[Sample], params: &EvalParams) -> f64 {
+    let (mut low, mut high) = (0.0, 10.0);
+    while high - low > 1e-4 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if error(samples, params, a) < error(samples, params, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Texel's local search: every pass tries moving each parameter one unit up or down and keeps
+// the changes that lower the error, until a pass changes nothing or max_passes is reached.
+// report gets called after every pass with its number, the parameters and the error
+pub fn tune<F>(
+    samples: &[Sample],
+    params: &EvalParams,
+    k: f64,
+    max_passes: u32,
+    mut report: F,
+) -> EvalParams
+where
+    F: FnMut(u32, &EvalParams, f64),
+{
+    let mut values = params.values();
+    let mut best = error(samples, params, k);
+    for pass in 1..=max_passes {
+        let mut improved = false;
+        for i in 0..PARAM_COUNT {
+            for &step in [1, -1].iter() {
+                let mut candidate = values;
+                candidate[i] += step;
+                let candidate_error = error(samples, &EvalParams::from_values(candidate), k);
+                if candidate_error < best {
+                    best = candidate_error;
+                    values = candidate;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        report(pass, &EvalParams::from_values(values), best);
+        if !improved {
+            break;
+        }
+    }
+    EvalParams::from_values(values)
+}
+
+#[test]
+fn test_parse_sample() {
+    let sample = parse_sample("4k3/8/8/8/8/8/8/3QK3 w - - 0 1 1-0").unwrap();
+    assert_eq!(sample.result, 1.0);
+    assert_eq!(sample.terms[4], 1);
+    let sample = parse_sample("4k3/8/8/8/8/8/8/3QK3 w - - [0.5]").unwrap();
+    assert_eq!(sample.result, 0.5);
+    assert_eq!(
+        parse_sample("4k3/8/8/8/8/8/8/3QK3 w - - \"0-1\";")
+            .unwrap()
+            .result,
+        0.0
+    );
+    assert!(parse_sample("4k3/8/8/8/8/8/8/3QK3 w - - 2-0").is_err());
+    assert!(parse_sample("4k3/8/8/8/8/8/8/3QK3 w 1-0").is_err());
+    assert!(parse_sample("1-0").is_err());
+}
+
+#[test]
+fn test_tune() {
+    // An extra knight wins most of the time, an extra pawn only some of the time
+    let mut samples = Vec::new();
+    for &(fen, wins, draws) in [
+        ("4k3/8/8/8/8/8/8/1N2K3 w - -", 8, 2),
+        ("1n2k3/8/8/8/8/8/8/4K3 w - -", 0, 2),
+        ("4k3/8/8/8/8/8/4P3/4K3 w - -", 3, 7),
+        ("4k3/4p3/8/8/8/8/8/4K3 w - -", 0, 7),
+    ]
+    .iter()
+    {
+        for i in 0..10 {
+            let result = if i < wins {
+                "1-0"
+            } else if i < wins + draws {
+                "1/2-1/2"
+            } else {
+                "0-1"
+            };
+            samples.push(parse_sample(&format!("{} {}", fen, result)).unwrap());
+        }
+    }
+    let params = EvalParams {
+        mobility: 0,
+        ..EvalParams::default()
+    };
+    let k = fit_k(&samples, &params);
+    assert!(k > 0.0);
+    let mut passes = 0;
+    let tuned = tune(&samples, &params, k, 200, |pass, _, _| passes = pass);
+    assert!(passes > 1);
+    assert!(error(&samples, &tuned, k) < error(&samples, &params, k));
+    assert!(tuned.knight > tuned.pawn);
+}
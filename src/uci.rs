@@ -0,0 +1,371 @@
+use std::time::Duration;
+
+use book::Book;
+use eval::EvalParams;
+use game::Color::{Black, White};
+use game::*;
+use lurri;
+use ponder::{self, Input, Reaction};
+use tablebase::Tablebase;
+use time::Clock;
+
+pub fn uci() {
+    let mut game: Game = Game::new();
+    let mut engine = lurri::Engine::new();
+    let mut chess960 = false;
+    let mut input = Input::stdin();
+    identify();
+    while let Some(line) = input.next_line() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => identify(),
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                game = Game::new();
+                engine.clear();
+            }
+            Some("setoption") => {
+                if let Err(e) = set_option(&mut engine, &mut chess960, &mut tokens) {
+                    println!("info string {}", e);
+                }
+            }
+            Some("position") => match parse_position(&mut tokens, chess960) {
+                Ok(new_game) => game = new_game,
+                Err(e) => println!("info string {}", e),
+            },
+            Some("go") => go(&engine, &game, &mut tokens, &mut input),
+            Some("quit") => break,
+            _ => {}
+        }
+    }
+}
+
+fn identify() {
+    println!("id name Lurri");
+    println!("id author Lucas David Traverso");
+    println!(
+        "option name Threads type spin default 1 min 1 max {}",
+        lurri::MAX_THREADS
+    );
+    println!("option name Hash type spin default 16 min 1 max 4096");
+    println!("option name BookFile type string default <empty>");
+    println!("option name BookDepth type spin default 20 min 0 max 1000");
+    println!("option name SyzygyPath type string default <empty>");
+    println!("option name EvalFile type string default <empty>");
+    println!("option name UCI_Chess960 type check default false");
+    println!("option name Ponder type check default false");
+    println!("uciok");
+}
+
+// In Chess960 castling moves are written as the king taking its rook
+fn parse_position<'a, I>(tokens: &mut I, chess960: bool) -> Result<Game, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut game = match tokens.next() {
+        Some("startpos") => {
+            match tokens.next() {
+                None | Some("moves") => {}
+                Some(token) => return Err(format!("Unexpected {}", token)),
+            }
+            Game::new()
+        }
+        Some("fen") => {
+            let fen: Vec<&str> = tokens.by_ref().take_while(|&t| t != "moves").collect();
+            Game::from_fen(&fen.join(" ")).map_err(|e| e.to_string())?
+        }
+        _ => return Err("Expected startpos or fen".to_string()),
+    };
+    game.chess960 |= chess960;
+    play_moves(game, tokens)
+}
+
+fn play_moves<'a, I>(mut game: Game, tokens: &mut I) -> Result<Game, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    for token in tokens {
+        let mov = game
+            .parse_move(token)
+            .map_err(|e| format!("{} ({})", e, token))?;
+        game.make_move(&mov)
+            .map_err(|e| format!("{} ({})", e, token))?;
+    }
+    Ok(game)
+}
+
+// setoption name <name> value <value>, option names are case insensitive
+fn set_option<'a, I>(
+    engine: &mut lurri::Engine,
+    chess960: &mut bool,
+    tokens: &mut I,
+) -> Result<(), String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    if tokens.next() != Some("name") {
+        return Err("Expected name".to_string());
+    }
+    let name: Vec<&str> = tokens.by_ref().take_while(|&t| t != "value").collect();
+    let name = name.join(" ").to_lowercase();
+    let value: Vec<&str> = tokens.collect();
+    let value = value.join(" ");
+    let number = || {
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("Bad value for {}: {}", name, value))
+    };
+    match name.as_ref() {
+        "threads" => engine.set_threads(number()?),
+        "hash" => engine.set_hash(number()?),
+        "bookfile" if value.is_empty() || value == "<empty>" => engine.set_book(None),
+        "bookfile" => engine.set_book(Some(
+            Book::open(&value).map_err(|e| format!("Couldn't load book, {}", e))?,
+        )),
+        "bookdepth" => engine.set_book_depth(number()? as u32),
+        "syzygypath" if value.is_empty() || value == "<empty>" => engine.set_tablebase(None),
+        "syzygypath" => engine.set_tablebase(Some(
+            Tablebase::open(&value).map_err(|e| format!("Couldn't load tablebases, {}", e))?,
+        )),
+        "evalfile" if value.is_empty() || value == "<empty>" => {
+            engine.set_eval_params(EvalParams::default())
+        }
+        "evalfile" => engine.set_eval_params(
+            EvalParams::load(&value)
+                .map_err(|e| format!("Couldn't load evaluation parameters, {}", e))?,
+        ),
+        "uci_chess960" => *chess960 = value == "true",
+        // Whether to ponder is up to the interface, which sends go ponder
+        "ponder" => {}
+        _ => return Err(format!("Unknown option {}", name)),
+    }
+    Ok(())
+}
+
+fn go<'a, I>(engine: &lurri::Engine, game: &Game, tokens: &mut I, input: &mut Input)
+where
+    I: Iterator<Item = &'a str>,
+{
+    let go = parse_go(tokens, game.turn);
+    let waits = go.ponder || go.infinite;
+    let limits = if waits {
+        lurri::SearchLimits::depth(lurri::MAX_DEPTH)
+    } else {
+        go.limits
+    };
+    let (mut result, mut stopped_by) = search(engine, game, limits, input);
+    // Pondering and infinite searches only give their move after stop or ponderhit
+    while waits && stopped_by.is_none() {
+        match input.next_line() {
+            Some(line) => match during_search(&line) {
+                Reaction::Continue => {}
+                Reaction::Postpone => {
+                    input.unread(line);
+                    break;
+                }
+                _ => stopped_by = Some(line.trim().to_string()),
+            },
+            None => break,
+        }
+    }
+    if go.ponder && stopped_by.as_deref() == Some("ponderhit") {
+        // The table still holds the ponder search, so the real one gets back to its depth
+        // quickly
+        result = search(engine, game, go.limits, input).0;
+    }
+    println!("{}", bestmove(game, &result));
+}
+
+// Also returns the line that stopped the search, if any did
+fn search(
+    engine: &lurri::Engine,
+    game: &Game,
+    limits: lurri::SearchLimits,
+    input: &mut Input,
+) -> (lurri::SearchResult, Option<String>) {
+    let mut stopped_by = None;
+    let report = |result: &lurri::SearchResult| println!("{}", info(game, result));
+    let result = input.search_in_background(engine, game, limits, report, |line| {
+        let reaction = during_search(line);
+        if stopped_by.is_none() && reaction != Reaction::Continue {
+            stopped_by = Some(line.trim().to_string());
+        }
+        reaction
+    });
+    (result, stopped_by)
+}
+
+// Any command but isready, stop and ponderhit ends the search and gets handled after it
+fn during_search(line: &str) -> Reaction {
+    match line.trim() {
+        "isready" => {
+            println!("readyok");
+            Reaction::Continue
+        }
+        "stop" | "ponderhit" => Reaction::Stop,
+        _ => Reaction::Postpone,
+    }
+}
+
+// The expected reply goes along as the move to ponder on
+fn bestmove(game: &Game, result: &lurri::SearchResult) -> String {
+    let mov = match result.best_move {
+        Some(mov) => mov,
+        None => return "bestmove 0000".to_string(),
+    };
+    let mut after = game.clone();
+    match ponder::expected_reply(game, result) {
+        Some(reply) if after.make_move(&mov).is_ok() => format!(
+            "bestmove {} ponder {}",
+            game.format_move(&mov),
+            after.format_move(&reply)
+        ),
+        _ => format!("bestmove {}", game.format_move(&mov)),
+    }
+}
+
+// What go asks for. Ponder and infinite searches go on until stop, and ponderhit turns the
+// ponder search into a regular one with the limits
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Go {
+    limits: lurri::SearchLimits,
+    ponder: bool,
+    infinite: bool,
+}
+
+// A move time wins over the clocks, and without either the search goes to a fixed depth
+fn parse_go<'a, I>(tokens: &mut I, turn: Color) -> Go
+where
+    I: Iterator<Item = &'a str>,
+{
+    let (mut ponder, mut infinite) = (false, false);
+    let mut depth = None;
+    let mut movetime = None;
+    let mut clocks = [None; 2];
+    let mut increments = [Duration::from_secs(0); 2];
+    let mut moves_to_go = None;
+    while let Some(token) = tokens.next() {
+        // Some interfaces send negative times once the clock runs out
+        let mut millis = || {
+            tokens
+                .next()
+                .and_then(|n| n.parse::<i64>().ok())
+                .map(|n| Duration::from_millis(n.max(0) as u64))
+        };
+        match token {
+            "depth" => depth = tokens.next().and_then(|n| n.parse().ok()),
+            "movestogo" => moves_to_go = tokens.next().and_then(|n| n.parse().ok()),
+            "movetime" => movetime = millis(),
+            "wtime" => clocks[White.index()] = millis(),
+            "btime" => clocks[Black.index()] = millis(),
+            "winc" => increments[White.index()] = millis().unwrap_or_default(),
+            "binc" => increments[Black.index()] = millis().unwrap_or_default(),
+            "ponder" => ponder = true,
+            "infinite" => infinite = true,
+            _ => {}
+        }
+    }
+    let mut limits = match (movetime, clocks[turn.index()]) {
+        (Some(time), _) => lurri::SearchLimits::time(time),
+        (None, Some(remaining)) => lurri::SearchLimits::clock(Clock {
+            remaining,
+            increment: increments[turn.index()],
+            moves_to_go,
+        }),
+        (None, None) => lurri::SearchLimits::depth(lurri::DEFAULT_DEPTH),
+    };
+    if let Some(depth) = depth {
+        limits.depth = depth;
+    }
+    Go {
+        limits,
+        ponder,
+        infinite,
+    }
+}
+
+fn info(game: &Game, result: &lurri::SearchResult) -> String {
+    let score = match result.mate_in(game.turn) {
+        Some(n) => format!("mate {}", n),
+        None => format!("cp {}", result.centipawns(game.turn)),
+    };
+    format!(
+        "info depth {} score {} nodes {} nps {} tbhits {} time {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nps(),
+        result.tb_hits,
+        result.elapsed.as_millis(),
+        result.pv_string(game)
+    )
+}
+
+#[test]
+fn test_parse_position() {
+    let mut tokens = "startpos moves e2e4 e7e5 g1f3".split_whitespace();
+    let game = parse_position(&mut tokens, false).unwrap();
+    assert_eq!(
+        game.to_fen(),
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    );
+
+    let mut tokens = "fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1g1".split_whitespace();
+    let game = parse_position(&mut tokens, false).unwrap();
+    assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+
+    let mut tokens = "startpos moves e2e5".split_whitespace();
+    assert!(parse_position(&mut tokens, false).is_err());
+
+    let mut tokens = "fen 4k3/8/8/8/8/8/8/1R4KR w HB - 0 1 moves g1h1".split_whitespace();
+    let game = parse_position(&mut tokens, true).unwrap();
+    assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/1R3RK1 b - - 1 1");
+    let mut tokens = "fen 4k3/8/8/8/8/8/8/1R4KR w HB - 0 1 moves g1b1".split_whitespace();
+    let game = parse_position(&mut tokens, true).unwrap();
+    assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/2KR3R b - - 1 1");
+}
+
+#[test]
+fn test_set_option() {
+    let mut engine = lurri::Engine::new();
+    let mut chess960 = false;
+    let mut tokens = "name Threads value 3".split_whitespace();
+    assert!(set_option(&mut engine, &mut chess960, &mut tokens).is_ok());
+    assert_eq!(engine.threads(), 3);
+    let mut tokens = "name Threads value many".split_whitespace();
+    assert!(set_option(&mut engine, &mut chess960, &mut tokens).is_err());
+    let mut tokens = "name Ponder value true".split_whitespace();
+    assert!(set_option(&mut engine, &mut chess960, &mut tokens).is_ok());
+    let mut tokens = "name Contempt value 10".split_whitespace();
+    assert!(set_option(&mut engine, &mut chess960, &mut tokens).is_err());
+    let mut tokens = "name UCI_Chess960 value true".split_whitespace();
+    assert!(set_option(&mut engine, &mut chess960, &mut tokens).is_ok());
+    assert!(chess960);
+}
+
+#[test]
+fn test_parse_go() {
+    let go = parse_go(&mut "depth 6".split_whitespace(), White);
+    assert_eq!(go.limits, lurri::SearchLimits::depth(6));
+    assert!(!go.ponder && !go.infinite);
+    let limits = parse_go(&mut "".split_whitespace(), White).limits;
+    assert_eq!(limits, lurri::SearchLimits::depth(lurri::DEFAULT_DEPTH));
+    let limits = parse_go(&mut "movetime 500 wtime 1000".split_whitespace(), White).limits;
+    assert_eq!(limits.time, Some(Duration::from_millis(500)));
+    assert_eq!(limits.clock, None);
+
+    let tokens = "wtime 60000 btime -20 winc 1000 binc 500 movestogo 12";
+    let clock = parse_go(&mut tokens.split_whitespace(), White)
+        .limits
+        .clock
+        .unwrap();
+    assert_eq!(clock.remaining, Duration::from_secs(60));
+    assert_eq!(clock.increment, Duration::from_secs(1));
+    assert_eq!(clock.moves_to_go, Some(12));
+    let clock = parse_go(&mut tokens.split_whitespace(), Black)
+        .limits
+        .clock
+        .unwrap();
+    assert_eq!(clock.remaining, Duration::from_secs(0));
+    assert_eq!(clock.increment, Duration::from_millis(500));
+}
@@ -0,0 +1,500 @@
+use std::fmt;
+
+use crazyhouse::Crazyhouse;
+use eval::EvalParams;
+use game::Color::{Black, White};
+use game::PieceType::King;
+use game::*;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Outcome {
+    Win(Color),
+    Draw,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Win(color) => write!(f, "{} wins", color),
+            Outcome::Draw => write!(f, "Draw"),
+        }
+    }
+}
+
+impl Outcome {
+    // A game that is already over, as a win in 0 plies
+    pub fn value(self) -> BoardValue {
+        match self {
+            Outcome::Win(White) => BoardValue::WonWhite(0),
+            Outcome::Win(Black) => BoardValue::WonBlack(0),
+            Outcome::Draw => BoardValue::Value(0),
+        }
+    }
+}
+
+// The rules of a chess variant. Every method defaults to standard chess, so a variant only
+// overrides what it changes
+pub trait Rules: Sync {
+    fn start_position(&self) -> Game {
+        Game::new()
+    }
+
+    // Moves for the side to move that follow the way pieces move, make_move then drops the
+    // ones that is_legal rejects
+    fn generate_moves(&self, game: &Game) -> Vec<ValuedMove> {
+        game.standard_moves()
+    }
+
+    // Whether a move may lead to this position, where the side that moved is no longer the one
+    // to move. In standard chess it can't leave its own king in check
+    fn is_legal(&self, after: &Game) -> bool {
+        !after.can_eat_king()
+    }
+
+    // Keeps whatever state the variant needs past the board, once a move has been made and the
+    // turn has passed to the other side
+    fn after_move(&self, _game: &mut Game, _mov: &Move, _captured: Option<Piece>) {}
+
+    // From the point of view of white. Variants add their own terms to steer the search
+    // towards their goal, those are not tuned
+    fn evaluate(&self, game: &Game, params: &EvalParams) -> i32 {
+        game.standard_evaluation(params)
+    }
+
+    // Whether a capture must be made whenever there is one. The search can't stand pat or
+    // pass then, the side to move may have to give material away
+    fn forced_captures(&self) -> bool {
+        false
+    }
+
+    // Results reached through the goal of the variant, that don't depend on the moves left
+    fn goal(&self, _game: &Game) -> Option<Outcome> {
+        None
+    }
+
+    // The result when the side to move has no legal moves
+    fn no_moves(&self, game: &Game) -> Outcome {
+        if game.is_check() {
+            Outcome::Win(!game.turn)
+        } else {
+            Outcome::Draw
+        }
+    }
+
+    // What makes a position set up from outside impossible in the variant, besides what
+    // Game::validate checks for all of them. In standard chess each side has one king and the
+    // side that just moved can't be in check
+    fn validate(&self, game: &Game) -> Vec<SetupError> {
+        let mut problems = Vec::new();
+        for &color in [White, Black].iter() {
+            let kings = Position::all()
+                .filter(|&pos| game.get_square(pos).contains(Piece::new(color, King)))
+                .count();
+            if kings == 0 {
+                problems.push(SetupError::MissingKing(color));
+            } else if kings > 1 {
+                problems.push(SetupError::TooManyKings(color));
+            }
+        }
+        if game.can_eat_king() {
+            problems.push(SetupError::OpponentInCheck(!game.turn));
+        }
+        problems
+    }
+}
+
+pub struct Standard;
+
+impl Rules for Standard {}
+
+// Besides by checkmate, a player wins by bringing the king to one of the four center squares
+pub struct KingOfTheHill;
+
+pub const HILL: [(char, char); 4] = [('d', '4'), ('e', '4'), ('d', '5'), ('e', '5')];
+
+// Bonus for a king by the number of king moves it needs to reach the hill
+const HILL_BONUS: [i32; 4] = [0, 15, 6, 2];
+
+fn hill_distance(pos: Position) -> usize {
+    HILL.iter()
+        .map(|&(x, y)| {
+            let hill = Position::safe_from_chars(x, y);
+            (pos.x - hill.x).abs().max((pos.y - hill.y).abs()) as usize
+        })
+        .min()
+        .unwrap()
+}
+
+fn hill_bonus(game: &Game) -> i32 {
+    Position::all()
+        .filter_map(|pos| match game.get_piece(pos) {
+            Some(piece!(color, King)) => {
+                Some(color.get_sign() * HILL_BONUS.get(hill_distance(pos)).cloned().unwrap_or(0))
+            }
+            _ => None,
+        })
+        .sum()
+}
+
+impl Rules for KingOfTheHill {
+    fn evaluate(&self, game: &Game, params: &EvalParams) -> i32 {
+        game.standard_evaluation(params) + hill_bonus(game)
+    }
+
+    fn goal(&self, game: &Game) -> Option<Outcome> {
+        for &color in [White, Black].iter() {
+            let on_hill = HILL.iter().any(|&(x, y)| {
+                game.get_square(Position::safe_from_chars(x, y))
+                    .contains(Piece::new(color, King))
+            });
+            if on_hill {
+                return Some(Outcome::Win(color));
+            }
+        }
+        None
+    }
+}
+
+// Besides by checkmate, a player wins by giving check for the third time
+pub struct ThreeCheck;
+
+pub const CHECKS_TO_WIN: u32 = 3;
+
+// Bonus by the number of checks already given
+const CHECK_BONUS: [i32; 3] = [0, 10, 30];
+
+impl Rules for ThreeCheck {
+    fn after_move(&self, game: &mut Game, _mov: &Move, _captured: Option<Piece>) {
+        if game.is_check() {
+            game.checks[(!game.turn).index()] += 1;
+        }
+    }
+
+    fn evaluate(&self, game: &Game, params: &EvalParams) -> i32 {
+        let bonus = |color: Color| {
+            CHECK_BONUS
+                .get(game.checks[color.index()] as usize)
+                .cloned()
+                .unwrap_or(0)
+        };
+        game.standard_evaluation(params) + bonus(White) - bonus(Black)
+    }
+
+    fn goal(&self, game: &Game) -> Option<Outcome> {
+        [White, Black]
+            .iter()
+            .find(|color| game.checks[color.index()] >= CHECKS_TO_WIN)
+            .map(|&color| Outcome::Win(color))
+    }
+}
+
+// Also known as losing chess. Captures are forced, the king is an ordinary piece that pawns
+// can promote to and there is no castling. A player wins by losing every piece or by having no
+// moves left
+pub struct Antichess;
+
+impl Rules for Antichess {
+    fn start_position(&self) -> Game {
+        Game {
+            castling: CastlingRights::none(),
+            ..Game::new()
+        }
+    }
+
+    fn generate_moves(&self, game: &Game) -> Vec<ValuedMove> {
+        let mut moves = game.standard_moves();
+        let kings: Vec<ValuedMove> = moves
+            .iter()
+            .filter(|m| m.mov.tipo == MoveType::Promotion(PieceType::Queen))
+            .map(|m| ValuedMove::new(m.mov.from, m.mov.to, MoveType::Promotion(King)))
+            .collect();
+        moves.extend(kings);
+        if moves.iter().any(|m| game.is_capture(&m.mov)) {
+            moves.retain(|m| game.is_capture(&m.mov));
+        }
+        moves
+    }
+
+    fn is_legal(&self, _after: &Game) -> bool {
+        true
+    }
+
+    fn forced_captures(&self) -> bool {
+        true
+    }
+
+    // Kings can be taken and promoted to, any number of them goes
+    fn validate(&self, _game: &Game) -> Vec<SetupError> {
+        Vec::new()
+    }
+
+    // Every piece is a liability, whatever its kind
+    fn evaluate(&self, game: &Game, params: &EvalParams) -> i32 {
+        Position::all()
+            .filter_map(|pos| game.get_piece(pos))
+            .map(|piece| -piece.color.get_sign() * params.pawn)
+            .sum()
+    }
+
+    fn goal(&self, game: &Game) -> Option<Outcome> {
+        [White, Black]
+            .iter()
+            .find(|&&color| !Position::all().any(|pos| game.get_square(pos).has_color(color)))
+            .map(|&color| Outcome::Win(color))
+    }
+
+    fn no_moves(&self, game: &Game) -> Outcome {
+        Outcome::Win(game.turn)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Variant {
+    Standard,
+    KingOfTheHill,
+    ThreeCheck,
+    Antichess,
+    Crazyhouse,
+}
+
+pub const VARIANTS: [Variant; 5] = [
+    Variant::Standard,
+    Variant::KingOfTheHill,
+    Variant::ThreeCheck,
+    Variant::Antichess,
+    Variant::Crazyhouse,
+];
+
+// Other names for the same rules. xboard knows antichess as suicide and giveaway
+pub const ALIASES: [(&str, Variant); 2] = [
+    ("suicide", Variant::Antichess),
+    ("giveaway", Variant::Antichess),
+];
+
+impl Variant {
+    pub fn rules(self) -> &'static dyn Rules {
+        match self {
+            Variant::Standard => &Standard,
+            Variant::KingOfTheHill => &KingOfTheHill,
+            Variant::ThreeCheck => &ThreeCheck,
+            Variant::Antichess => &Antichess,
+            Variant::Crazyhouse => &Crazyhouse,
+        }
+    }
+
+    // Names as used by xboard
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "normal",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        VARIANTS
+            .iter()
+            .cloned()
+            .find(|variant| variant.name() == name)
+            .or_else(|| {
+                ALIASES
+                    .iter()
+                    .find(|&&(alias, _)| alias == name)
+                    .map(|&(_, variant)| variant)
+            })
+    }
+
+    pub fn start_position(self) -> Game {
+        let mut game = self.rules().start_position();
+        game.variant = self;
+        game
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Game {
+    pub fn rules(&self) -> &'static dyn Rules {
+        self.variant.rules()
+    }
+
+    // The result if the game is over, by the goal of the variant or for lack of moves
+    pub fn outcome(&self) -> Option<Outcome> {
+        let rules = self.rules();
+        rules.goal(self).or_else(|| {
+            if self.legal_moves().is_empty() {
+                Some(rules.no_moves(self))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[test]
+fn test_standard_outcome() {
+    let game = Variant::Standard.start_position();
+    assert_eq!(game.outcome(), None);
+    assert_eq!(game.legal_moves().len(), 20);
+    let mate = Game::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(mate.outcome(), Some(Outcome::Win(White)));
+    let stalemate = Game::from_fen("7k/8/6QK/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(stalemate.outcome(), Some(Outcome::Draw));
+}
+
+#[test]
+fn test_king_of_the_hill() {
+    assert_eq!(
+        Variant::from_name("kingofthehill"),
+        Some(Variant::KingOfTheHill)
+    );
+    assert_eq!(Variant::from_name("bughouse"), None);
+    let mut game = Variant::KingOfTheHill.start_position();
+    assert_eq!(game.variant, Variant::KingOfTheHill);
+    assert_eq!(game.outcome(), None);
+
+    game = Game::from_fen("7k/8/8/8/8/3K4/8/8 w - - 0 1").unwrap();
+    game.variant = Variant::KingOfTheHill;
+    assert_eq!(game.outcome(), None);
+    game.make_move(&Move::safe_from_string("d3e4")).unwrap();
+    assert_eq!(game.outcome(), Some(Outcome::Win(White)));
+    // The same move means nothing in standard chess
+    game.variant = Variant::Standard;
+    assert_eq!(game.outcome(), None);
+}
+
+#[test]
+fn test_search_king_of_the_hill() {
+    let mut game = Game::from_fen("k7/8/8/8/8/3K4/8/7r w - - 0 1").unwrap();
+    let standard = ::lurri::Engine::new().get_move(&game, 3);
+    assert!(matches!(standard.score, BoardValue::Value(_)));
+    game.variant = Variant::KingOfTheHill;
+    let result = ::lurri::Engine::new().get_move(&game, 3);
+    assert_eq!(result.score, BoardValue::WonWhite(1));
+    let mov = result.best_move.unwrap();
+    assert!(HILL
+        .iter()
+        .any(|&(x, y)| mov.to == Position::safe_from_chars(x, y)));
+}
+
+#[test]
+fn test_three_check() {
+    assert_eq!(Variant::from_name("3check"), Some(Variant::ThreeCheck));
+    let mut game = Variant::ThreeCheck.start_position();
+    for mov in [
+        "e2e4", "e7e5", "f1b5", "c7c6", "b5c6", "d7c6", "d1h5", "g8f6", "h5f7",
+    ]
+    .iter()
+    {
+        game.make_move(&game.parse_move(mov).unwrap()).unwrap();
+    }
+    // Bxc6 doesn't check with the d pawn in the way, Qxf7 does
+    assert_eq!(game.checks, [1, 0]);
+    assert_eq!(game.outcome(), None);
+    let params = ::eval::EvalParams::default();
+    assert_eq!(
+        game.evaluate_with(&params) - game.standard_evaluation(&params),
+        CHECK_BONUS[1]
+    );
+    let mut standard = game.clone();
+    standard.checks = [0, 0];
+    assert!(game.hash() != standard.hash());
+
+    // White needs one more check, black all three
+    let mut game = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 1+3 0 1").unwrap();
+    assert_eq!(game.outcome(), None);
+    let result = ::lurri::Engine::new().get_move(&game, 2);
+    assert_eq!(result.score, BoardValue::WonWhite(1));
+    game.make_move(&result.best_move.unwrap()).unwrap();
+    assert_eq!(game.checks, [3, 0]);
+    assert_eq!(game.outcome(), Some(Outcome::Win(White)));
+    assert_eq!(game.outcome().unwrap().value(), BoardValue::WonWhite(0));
+    assert_eq!(::lurri::Engine::new().get_move(&game, 2).best_move, None);
+}
+
+#[test]
+fn test_hill_evaluation() {
+    let mut game = Game::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+    game.variant = Variant::KingOfTheHill;
+    assert_eq!(hill_bonus(&game), 0);
+    game = Game::from_fen("7k/8/8/8/8/8/2K5/8 w - - 0 1").unwrap();
+    game.variant = Variant::KingOfTheHill;
+    assert_eq!(hill_bonus(&game), HILL_BONUS[2] - HILL_BONUS[3]);
+    let params = ::eval::EvalParams::default();
+    assert_eq!(
+        game.evaluate_with(&params),
+        game.standard_evaluation(&params) + hill_bonus(&game)
+    );
+}
+
+#[test]
+fn test_antichess() {
+    assert_eq!(Variant::from_name("antichess"), Some(Variant::Antichess));
+    assert_eq!(Variant::from_name("suicide"), Some(Variant::Antichess));
+    assert_eq!(Variant::from_name("giveaway"), Some(Variant::Antichess));
+    let mut game = Variant::Antichess.start_position();
+    assert!(!game.castling.any());
+    game.make_move(&Move::safe_from_string("e2e4")).unwrap();
+    game.make_move(&Move::safe_from_string("d7d5")).unwrap();
+    assert_eq!(game.legal_moves(), vec![Move::safe_from_string("e4d5")]);
+    assert_eq!(
+        game.clone().make_move(&Move::safe_from_string("a2a3")),
+        Err(MoveError::MustCapture)
+    );
+    assert!(game
+        .clone()
+        .make_move(&Move::safe_from_string("e4d5"))
+        .is_ok());
+
+    let setup = |pieces: &[((char, char), Piece)], turn| {
+        let mut game = Variant::Antichess.start_position();
+        game.clear();
+        for &((x, y), piece) in pieces.iter() {
+            game.set_square(Position::safe_from_chars(x, y), Some(piece))
+                .unwrap();
+        }
+        game.turn = turn;
+        game
+    };
+    let game = setup(&[(('e', '7'), Piece::new(White, PieceType::Pawn))], White);
+    assert!(game
+        .legal_moves()
+        .contains(&Move::safe_from_string("e7e8k")));
+    // Nothing left to lose
+    let game = setup(&[(('e', '1'), Piece::new(White, King))], White);
+    assert_eq!(game.outcome(), Some(Outcome::Win(Black)));
+    // No moves left wins, even with pieces on the board
+    let game = setup(
+        &[
+            (('e', '2'), Piece::new(White, PieceType::Pawn)),
+            (('e', '3'), Piece::new(Black, PieceType::Pawn)),
+            (('a', '8'), Piece::new(Black, PieceType::Rook)),
+        ],
+        White,
+    );
+    assert_eq!(game.outcome(), Some(Outcome::Win(White)));
+}
+
+#[test]
+fn test_search_antichess() {
+    // White gets rid of its rook by putting it where the black one has to take it
+    let mut game = Variant::Antichess.start_position();
+    game.clear();
+    for &((x, y), color) in [(('a', '1'), White), (('h', '8'), Black)].iter() {
+        game.set_square(
+            Position::safe_from_chars(x, y),
+            Some(Piece::new(color, PieceType::Rook)),
+        )
+        .unwrap();
+    }
+    let result = ::lurri::Engine::new().get_move(&game, 3);
+    assert_eq!(result.score, BoardValue::WonWhite(2));
+    game.make_move(&result.best_move.unwrap()).unwrap();
+    assert_eq!(game.legal_moves().len(), 1);
+}
@@ -0,0 +1,159 @@
+use crazyhouse::POCKET_PIECES;
+use game::Color::{Black, White};
+use game::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
+use game::*;
+use variant::{Variant, CHECKS_TO_WIN, VARIANTS};
+
+const PIECE_KEYS: usize = 0;
+// One key per castling right and file of its rook, which tells Chess960 setups apart. The file
+// of the king is already in the pieces
+const CASTLING_KEYS: usize = PIECE_KEYS + 12 * 64;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 4 * 8;
+const TURN_KEY: usize = EN_PASSANT_KEYS + 8;
+// One key per colour for each count of checks given in Three-check, no checks has no key
+const CHECK_KEYS: usize = TURN_KEY + 1;
+// One key per colour, kind of piece and count in a Crazyhouse pocket, counts past the last
+// one share its key
+const POCKET_KEYS: usize = CHECK_KEYS + 2 * CHECKS_TO_WIN as usize;
+const POCKET_COUNTS: usize = 16;
+const PROMOTED_KEYS: usize = POCKET_KEYS + 2 * POCKET_PIECES.len() * POCKET_COUNTS;
+// One key per variant, standard chess has none. The same placement means something else in
+// another variant, and the transposition table outlives a change of variant
+const VARIANT_KEYS: usize = PROMOTED_KEYS + 64;
+const KEY_COUNT: usize = VARIANT_KEYS + VARIANTS.len();
+
+// Fixed seed, so hashes are the same in every run
+static KEYS: [u64; KEY_COUNT] = generate_keys(0x9E37_79B9_7F4A_7C15);
+
+const fn generate_keys(seed: u64) -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = seed;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        // xorshift64*
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        keys[i] = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        i += 1;
+    }
+    keys
+}
+
+fn piece_key(piece: Piece, pos: Position) -> u64 {
+    let kind = match piece.tipo {
+        King => 0,
+        Queen => 1,
+        Rook => 2,
+        Bishop => 3,
+        Knight => 4,
+        Pawn => 5,
+    } + match piece.color {
+        White => 0,
+        Black => 6,
+    };
+    KEYS[PIECE_KEYS + kind * 64 + pos.index()]
+}
+
+impl Game {
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0;
+        for pos in Position::all() {
+            if let Some(piece) = self.get_piece(pos) {
+                hash ^= piece_key(piece, pos);
+            }
+        }
+        let castling = self.castling;
+        for (i, &(right, file)) in [
+            (castling.white_short, castling.short_rook_file),
+            (castling.white_long, castling.long_rook_file),
+            (castling.black_short, castling.short_rook_file),
+            (castling.black_long, castling.long_rook_file),
+        ]
+        .iter()
+        .enumerate()
+        {
+            if right {
+                let file = (file - Position::ch2x('a')) as usize;
+                hash ^= KEYS[CASTLING_KEYS + i * 8 + file];
+            }
+        }
+        if let Some(pos) = self.en_passant {
+            hash ^= KEYS[EN_PASSANT_KEYS + pos.index() % 8];
+        }
+        if self.turn == White {
+            hash ^= KEYS[TURN_KEY];
+        }
+        for (i, &checks) in self.checks.iter().enumerate() {
+            if checks > 0 {
+                let checks = checks.min(CHECKS_TO_WIN) as usize;
+                hash ^= KEYS[CHECK_KEYS + i * CHECKS_TO_WIN as usize + checks - 1];
+            }
+        }
+        for (i, pocket) in self.pockets.iter().enumerate() {
+            if pocket.is_empty() {
+                continue;
+            }
+            for (j, &tipo) in POCKET_PIECES.iter().enumerate() {
+                let count = pocket.count(tipo) as usize;
+                if count > 0 {
+                    let slot = (i * POCKET_PIECES.len() + j) * POCKET_COUNTS;
+                    hash ^= KEYS[POCKET_KEYS + slot + count.min(POCKET_COUNTS) - 1];
+                }
+            }
+        }
+        let mut promoted = self.promoted;
+        while promoted != 0 {
+            hash ^= KEYS[PROMOTED_KEYS + promoted.trailing_zeros() as usize];
+            promoted &= promoted - 1;
+        }
+        if self.variant != Variant::Standard {
+            let variant = VARIANTS.iter().position(|&v| v == self.variant).unwrap();
+            hash ^= KEYS[VARIANT_KEYS + variant];
+        }
+        hash
+    }
+}
+
+#[test]
+fn test_hash() {
+    let play = |moves: &[&str]| {
+        let mut game = Game::new();
+        for mov in moves.iter() {
+            game.make_move(&Move::safe_from_string(mov)).unwrap();
+        }
+        game
+    };
+    let game = play(&["g1f3", "g8f6", "b1c3"]);
+    assert_eq!(game.hash(), play(&["b1c3", "g8f6", "g1f3"]).hash());
+    assert_eq!(game.hash(), Game::from_fen(&game.to_fen()).unwrap().hash());
+    assert!(game.hash() != play(&["b1c3", "g8f6"]).hash());
+    let mut other_side = game.clone();
+    other_side.turn = !game.turn;
+    assert!(game.hash() != other_side.hash());
+    assert_eq!(
+        Game::new().hash(),
+        play(&["g1f3", "g8f6", "f3g1", "f6g8"]).hash()
+    );
+
+    // The same pieces in different variants
+    let hashes: Vec<u64> = VARIANTS
+        .iter()
+        .map(|&variant| {
+            Game {
+                variant,
+                ..Game::new()
+            }
+            .hash()
+        })
+        .collect();
+    for (i, hash) in hashes.iter().enumerate() {
+        assert!(!hashes[i + 1..].contains(hash));
+    }
+    // And the same pieces with the castling rooks on other files
+    let inner = Game::from_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1").unwrap();
+    let mut outer = inner.clone();
+    outer.castling.short_rook_file = Position::ch2x('h');
+    outer.castling.long_rook_file = Position::ch2x('a');
+    assert!(inner.hash() != outer.hash());
+}
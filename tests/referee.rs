@@ -0,0 +1,55 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+
+const LURRI: &str = env!("CARGO_BIN_EXE_lurri");
+
+// Runs a console command in a fresh Lurri and returns everything it printed
+fn console(command: &str) -> String {
+    let mut child = Command::new(LURRI)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "{}\nquit", command).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_referee_match() {
+    let pgn = env::temp_dir().join(format!("lurri-referee-{}.pgn", process::id()));
+    let _ = fs::remove_file(&pgn);
+    let output = console(&format!(
+        "referee games=2 maxplies=8 tc=5+0.1 pgn={} uci:{} vs xboard:{}",
+        pgn.display(),
+        LURRI,
+        LURRI
+    ));
+    assert!(output.contains("Game 1 (Lurri vs Lurri): draw by move limit in 8 plies"));
+    assert!(output.contains("Result after 2 games: +0 =2 -0"));
+
+    let text = fs::read_to_string(&pgn).unwrap();
+    fs::remove_file(&pgn).unwrap();
+    assert_eq!(text.matches("[Event \"Lurri referee match\"]").count(), 2);
+    assert_eq!(text.matches("[Result \"1/2-1/2\"]").count(), 2);
+    assert_eq!(text.matches("[Termination \"adjudication\"]").count(), 2);
+    // The opening takes five plies and the engines play eight more
+    assert_eq!(text.matches("7. ").count(), 2);
+    assert!(!text.contains("8. "));
+    assert_eq!(text.matches("{Draw by move limit} 1/2-1/2").count(), 2);
+}
+
+#[test]
+fn test_referee_time_forfeit() {
+    // No engine process answers within a millisecond, so in both games black loses on time
+    // right after the opening
+    let output = console(&format!(
+        "referee games=2 tc=0.001 uci:{} vs uci:{}",
+        LURRI, LURRI
+    ));
+    assert!(output.contains("Game 1 (Lurri vs Lurri): first wins by time forfeit in 0 plies"));
+    assert!(output.contains("Game 2 (Lurri vs Lurri): second wins by time forfeit in 0 plies"));
+    assert!(output.contains("Result after 2 games: +1 =0 -1"));
+}